@@ -0,0 +1,122 @@
+//! Case style conversion. Text is split in words using any non alphanumeric character as separator
+//! and detecting case changes, e.g. `fooBar`, `HTMLParser` or `Version2Beta` contain two words.
+//! Digits are kept with the previous word.
+
+/// Case style to convert the words of a text.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Case {
+    /// `snake_case`
+    Snake,
+    /// `kebab-case`
+    Kebab,
+    /// `camelCase`
+    Camel,
+    /// `PascalCase`
+    Pascal,
+    /// `Title Case`
+    Title,
+    /// `Sentence case`
+    Sentence,
+}
+
+impl Case {
+    /// Convert text to this case style. Text without words is returned as it is.
+    pub fn convert(&self, text: &str) -> String {
+        let words = split_words(text);
+        if words.is_empty() {
+            return text.to_string();
+        }
+
+        let words = words.iter().enumerate().map(|(index, word)| match self {
+            Case::Snake | Case::Kebab => word.to_lowercase(),
+            Case::Camel if index == 0 => word.to_lowercase(),
+            Case::Sentence if index > 0 => word.to_lowercase(),
+            Case::Camel | Case::Pascal | Case::Title | Case::Sentence => capitalize(word),
+        });
+        let separator = match self {
+            Case::Snake => "_",
+            Case::Kebab => "-",
+            Case::Camel | Case::Pascal => "",
+            Case::Title | Case::Sentence => " ",
+        };
+        words.collect::<Vec<String>>().join(separator)
+    }
+}
+
+/// Split text in words.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in text.split(|c: char| !c.is_alphanumeric()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for (i, window) in chars.windows(2).enumerate() {
+            let ((_, current), (position, next)) = (window[0], window[1]);
+            // Lowercase or digit followed by uppercase, e.g. `fooBar` or `2Beta`
+            let case_change = !current.is_uppercase() && next.is_uppercase();
+            // Acronym followed by a word, e.g. `HTMLParser`
+            let acronym_end = current.is_uppercase()
+                && next.is_uppercase()
+                && chars.get(i + 2).is_some_and(|(_, c)| c.is_lowercase());
+            if case_change || acronym_end {
+                words.push(&part[start..position]);
+                start = position;
+            }
+        }
+        if start < part.len() {
+            words.push(&part[start..]);
+        }
+    }
+    words
+}
+
+/// Uppercase the first character of the word and lowercase the rest.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(
+            split_words("foo bar_baz-qux.quux"),
+            vec!["foo", "bar", "baz", "qux", "quux"]
+        );
+        assert_eq!(split_words("fooBarBaz"), vec!["foo", "Bar", "Baz"]);
+        assert_eq!(split_words("HTMLParser"), vec!["HTML", "Parser"]);
+        assert_eq!(split_words("parseHTML"), vec!["parse", "HTML"]);
+        assert_eq!(
+            split_words("HTML5Parser v2beta"),
+            vec!["HTML5", "Parser", "v2beta"]
+        );
+        assert_eq!(
+            split_words("Área51 ÑandúFeliz"),
+            vec!["Área51", "Ñandú", "Feliz"]
+        );
+        assert!(split_words(" --_").is_empty());
+    }
+
+    #[test]
+    fn convert_case() {
+        let text = "my HTMLParser_v2-final draft";
+        assert_eq!(Case::Snake.convert(text), "my_html_parser_v2_final_draft");
+        assert_eq!(Case::Kebab.convert(text), "my-html-parser-v2-final-draft");
+        assert_eq!(Case::Camel.convert(text), "myHtmlParserV2FinalDraft");
+        assert_eq!(Case::Pascal.convert(text), "MyHtmlParserV2FinalDraft");
+        assert_eq!(Case::Title.convert(text), "My Html Parser V2 Final Draft");
+        assert_eq!(
+            Case::Sentence.convert(text),
+            "My html parser v2 final draft"
+        );
+        assert_eq!(Case::Snake.convert("--"), "--");
+    }
+}
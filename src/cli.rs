@@ -1,4 +1,4 @@
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(version, about, author)]
@@ -34,6 +34,18 @@ pub struct CommonArgs {
     /// Generate file backups before renaming.
     #[arg(short, long)]
     pub backup: bool,
+    /// Undo already applied operations if any of them fails.
+    #[arg(long)]
+    pub rollback: bool,
+    /// Show the operations and apply them after a single confirmation.
+    #[arg(long, conflicts_with_all = ["dry_run", "silent", "emit_script"])]
+    pub confirm: bool,
+    /// Ask for confirmation of each rename, allowing to edit its target name.
+    #[arg(short, long, conflicts_with = "silent")]
+    pub interactive: bool,
+    /// Action when a source is modified or replaced before renaming it.
+    #[arg(value_enum, long, default_value_t = OnChange::Refuse, value_name = "ACTION")]
+    pub on_change: OnChange,
 
     /// Do not print any information.
     #[arg(short, long)]
@@ -45,12 +57,18 @@ pub struct CommonArgs {
     /// Force dumping operations into a file even in dry-run mode.
     #[arg(long, conflicts_with = "no_dump")]
     pub dump: bool,
-    /// Set the dump file prefix.
-    #[arg(long, conflicts_with = "no_dump", default_value = "rnr-")]
-    pub dump_prefix: String,
+    /// Set the dump file prefix [default: `rnr-` in the history directory].
+    #[arg(long, conflicts_with = "no_dump")]
+    pub dump_prefix: Option<String>,
     /// Do not dump operations into a file.
     #[arg(long)]
     pub no_dump: bool,
+    /// Do not write a journal to recover interrupted operations.
+    #[arg(long)]
+    pub no_journal: bool,
+    /// Print a script with the operations instead of applying them.
+    #[arg(value_enum, long, value_name = "SHELL", conflicts_with_all = ["force", "dump"])]
+    pub emit_script: Option<ScriptShell>,
 }
 
 #[derive(Args)]
@@ -71,6 +89,9 @@ pub struct PathArgs {
     /// Include hidden files and directories.
     #[arg(short = 'x', long, requires = "recursive")]
     pub hidden: bool,
+    /// Part of the path to replace.
+    #[arg(value_enum, long, default_value_t = Scope::Name)]
+    pub scope: Scope,
 }
 
 #[derive(Args)]
@@ -81,6 +102,18 @@ pub struct ReplaceArgs {
     /// Apply a transformation to replacements including captured groups.
     #[arg(value_enum, short = 't', long)]
     pub replace_transform: Option<ReplaceTransform>,
+    /// Order used to number matching paths in `{n}` placeholders.
+    #[arg(value_enum, long, default_value_t = NumberOrder::Path, value_name = "ORDER")]
+    pub number_order: NumberOrder,
+    /// Restart numbering in each directory.
+    #[arg(long)]
+    pub number_per_dir: bool,
+    /// Match the full path and move files to other directories, creating them if required.
+    #[arg(long, conflicts_with_all = ["scope", "include_dirs"])]
+    pub full_path: bool,
+    /// Fallback when the date is missing in `{exif.datetime}` placeholders.
+    #[arg(value_enum, long, default_value_t = ExifFallback::Skip, value_name = "FALLBACK")]
+    pub exif_fallback: ExifFallback,
 }
 
 #[derive(Subcommand)]
@@ -88,6 +121,32 @@ pub enum SubCommands {
     /// Rename files and directories using a regular expression.
     #[command(arg_required_else_help = true)]
     Regex(RegexArgs),
+    /// Rename files and directories applying the steps of a rules file in order.
+    #[command(arg_required_else_help = true)]
+    Rules {
+        /// JSON file with the rules.
+        #[arg(value_name = "RULESFILE")]
+        rules: String,
+
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        replace: ReplaceArgs,
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    /// Rename files and directories editing the list of paths in a text editor.
+    #[command(arg_required_else_help = true)]
+    Edit {
+        /// Editor command [default: $VISUAL or $EDITOR].
+        #[arg(long, value_name = "COMMAND")]
+        editor: Option<String>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        path: PathArgs,
+    },
     /// Read operations from a dump file.
     #[command(arg_required_else_help = true)]
     FromFile {
@@ -99,6 +158,56 @@ pub enum SubCommands {
         /// Undo the operations from the dump file.
         #[arg(short, long)]
         undo: bool,
+        /// Apply the operations to a moved or copied tree in this directory.
+        #[arg(long, value_name = "DIR")]
+        rebase: Option<String>,
+    },
+    /// Rename files and directories from a two-column CSV/TSV file with source and target paths.
+    #[command(arg_required_else_help = true)]
+    FromMap {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        #[arg(value_name = "MAPFILE")]
+        mapfile: String,
+        /// Column delimiter [default: tab for `.tsv` files, comma otherwise].
+        #[arg(long, value_name = "CHAR")]
+        delimiter: Option<char>,
+        /// Skip the first row as a header.
+        #[arg(long)]
+        header: bool,
+    },
+    /// List the batches of operations stored in the history directory.
+    History {
+        /// Set color output mode.
+        #[arg(value_enum, long, default_value_t = Color::Auto)]
+        color: Color,
+    },
+    /// Undo a batch of operations stored in the history directory.
+    #[command(arg_required_else_help = true)]
+    #[command(group(ArgGroup::new("batch").required(true).args(["last", "id"])))]
+    Undo {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Undo the last batch that was not undone yet.
+        #[arg(long)]
+        last: bool,
+        /// Undo the batch with the given identifier in the history.
+        #[arg(long, value_name = "ID")]
+        id: Option<usize>,
+    },
+    /// Complete or undo an interrupted batch of operations from its journal file.
+    #[command(arg_required_else_help = true)]
+    Recover {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        #[arg(value_name = "JOURNAL")]
+        journal: String,
+        /// Undo the applied operations instead of completing the batch.
+        #[arg(short, long)]
+        undo: bool,
     },
     /// Replace file name UTF-8 chars with ASCII chars representation.
     #[command(arg_required_else_help = true)]
@@ -106,6 +215,22 @@ pub enum SubCommands {
         #[command(flatten)]
         common: CommonArgs,
 
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    /// Convert file names to a case style.
+    #[command(arg_required_else_help = true)]
+    Case {
+        /// Case style.
+        #[arg(value_enum)]
+        style: CaseStyle,
+        /// Leave file extensions untouched (same as `--scope stem`).
+        #[arg(short = 'e', long, conflicts_with = "scope")]
+        keep_extension: bool,
+
+        #[command(flatten)]
+        common: CommonArgs,
+
         #[command(flatten)]
         path: PathArgs,
     },
@@ -124,6 +249,78 @@ pub enum ReplaceTransform {
     Upper,
     Lower,
     Ascii,
+    Snake,
+    Kebab,
+    Camel,
+    Pascal,
+    Title,
+    Sentence,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CaseStyle {
+    /// UPPERCASE
+    Upper,
+    /// lowercase
+    Lower,
+    /// snake_case
+    Snake,
+    /// kebab-case
+    Kebab,
+    /// camelCase
+    Camel,
+    /// PascalCase
+    Pascal,
+    /// Title Case
+    Title,
+    /// Sentence case
+    Sentence,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Scope {
+    /// File name.
+    Name,
+    /// File name without extension.
+    Stem,
+    /// File extension, including compressed tarballs ones like `tar.gz`.
+    Ext,
+    /// Full path as provided or found in recursive mode.
+    Path,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ScriptShell {
+    /// POSIX shell script.
+    Sh,
+    /// PowerShell script.
+    Powershell,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum OnChange {
+    /// Do not rename any file.
+    Refuse,
+    /// Skip the operation with a warning, and the ones depending on it.
+    Skip,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum NumberOrder {
+    /// Sort by path.
+    Path,
+    /// Sort by path comparing numbers by their value.
+    Natural,
+    /// Sort by modification time.
+    Mtime,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ExifFallback {
+    /// Skip the file with a warning.
+    Skip,
+    /// Use the modification time.
+    Mtime,
 }
 
 #[cfg(test)]
@@ -1,16 +1,25 @@
-use crate::cli::{Cli, RegexArgs};
+use crate::cli::{Cli, PathArgs, RegexArgs, ReplaceArgs};
+use crate::editor::default_editor;
 use crate::output::Printer;
-use anyhow::{Result, bail};
-use clap::Parser;
+use anyhow::{Result, anyhow, bail};
+use clap::{Parser, ValueEnum};
 use regex::Regex;
+use serde_derive::Deserialize;
 use std::{
+    fs,
     io::{self, IsTerminal},
+    path::PathBuf,
     sync::Arc,
 };
 
 use crate::{
-    cli::{ReplaceTransform, SubCommands},
+    case::Case,
+    cli::{
+        CaseStyle, Color, NumberOrder, OnChange, ReplaceTransform, Scope, ScriptShell, SubCommands,
+    },
+    history::{self, default_dump_prefix},
     renamer::TextTransformation,
+    template::Template,
 };
 
 /// This module is defined Config struct to carry application configuration. This struct is created
@@ -18,12 +27,21 @@ use crate::{
 /// considered.
 pub struct Config {
     pub force: bool,
+    pub confirm: bool,
     pub backup: bool,
+    pub rollback: bool,
+    pub on_change: OnChange,
+    pub interactive: bool,
     pub dirs: bool,
     pub dump: bool,
     pub dump_prefix: String,
+    pub journal: bool,
+    pub emit_script: Option<ScriptShell>,
+    pub full_path: bool,
     pub run_mode: RunMode,
     pub replace_mode: ReplaceMode,
+    pub number_order: NumberOrder,
+    pub number_per_dir: bool,
     pub printer: Printer,
 }
 
@@ -47,20 +65,60 @@ pub enum RunMode {
     FromFile {
         path: String,
         undo: bool,
+        rebase: Option<String>,
+    },
+    History {
+        dir: PathBuf,
+    },
+    /// Undo the batch stored in the given dump file of the history.
+    Undo {
+        path: String,
+    },
+    FromMap {
+        path: String,
+        delimiter: u8,
+        header: bool,
+    },
+    Recover {
+        path: String,
+        undo: bool,
     },
 }
 
 pub enum ReplaceMode {
     RegExp {
         expression: Regex,
-        replacement: String,
+        replacement: Template,
         limit: usize,
         transform: TextTransformation,
+        scope: Scope,
+    },
+    ToASCII {
+        scope: Scope,
+    },
+    Case {
+        transform: TextTransformation,
+        scope: Scope,
+    },
+    Rules(Vec<ReplaceMode>),
+    Editor {
+        command: String,
     },
-    ToASCII,
     None,
 }
 
+impl ReplaceMode {
+    /// Part of the path to replace.
+    pub fn scope(&self) -> Scope {
+        match self {
+            ReplaceMode::RegExp { scope, .. }
+            | ReplaceMode::ToASCII { scope }
+            | ReplaceMode::Case { scope, .. } => *scope,
+            ReplaceMode::Rules(_) | ReplaceMode::Editor { .. } | ReplaceMode::None => Scope::Name,
+        }
+    }
+}
+
 struct ArgumentParser<'a> {
     cli: &'a Cli,
     printer: &'a Printer,
@@ -69,14 +127,68 @@ struct ArgumentParser<'a> {
 impl ArgumentParser<'_> {
     fn parse_run_mode(&self) -> Result<RunMode> {
         let path = match &self.cli.command {
-            SubCommands::FromFile { dumpfile, undo, .. } => {
+            SubCommands::FromFile {
+                dumpfile,
+                undo,
+                rebase,
+                ..
+            } => {
                 return Ok(RunMode::FromFile {
                     path: dumpfile.clone(),
                     undo: *undo,
+                    rebase: rebase.clone(),
+                });
+            }
+            SubCommands::History { .. } => {
+                return Ok(RunMode::History {
+                    dir: self.history_dir()?,
+                });
+            }
+            SubCommands::Undo { id, .. } => {
+                let batch = match history::find_batch(&self.history_dir()?, *id) {
+                    Ok(batch) => batch,
+                    Err(err) => bail!(
+                        "{}{}",
+                        self.printer.colors.error.paint("Error: "),
+                        self.printer.colors.error.paint(err.message())
+                    ),
+                };
+                return Ok(RunMode::Undo {
+                    path: batch.path.to_string_lossy().to_string(),
+                });
+            }
+            SubCommands::FromMap {
+                mapfile,
+                delimiter,
+                header,
+                ..
+            } => {
+                let delimiter = match delimiter {
+                    Some(delimiter) if delimiter.is_ascii() => *delimiter as u8,
+                    Some(_) => bail!(
+                        "{}Bad delimiter provided, only ASCII characters are supported",
+                        self.printer.colors.error.paint("Error: ")
+                    ),
+                    None if mapfile.to_lowercase().ends_with(".tsv") => b'\t',
+                    None => b',',
+                };
+                return Ok(RunMode::FromMap {
+                    path: mapfile.clone(),
+                    delimiter,
+                    header: *header,
+                });
+            }
+            SubCommands::Recover { journal, undo, .. } => {
+                return Ok(RunMode::Recover {
+                    path: journal.clone(),
+                    undo: *undo,
                 });
             }
             SubCommands::Regex(RegexArgs { path, .. }) => path,
             SubCommands::ToASCII { path, .. } => path,
+            SubCommands::Case { path, .. } => path,
+            SubCommands::Rules { path, .. } => path,
+            SubCommands::Edit { path, .. } => path,
         };
 
         if path.recursive {
@@ -92,14 +204,60 @@ impl ArgumentParser<'_> {
 
     fn parse_replace_mode(&self) -> Result<ReplaceMode> {
         let regex = match &self.cli.command {
-            SubCommands::ToASCII { .. } => return Ok(ReplaceMode::ToASCII),
-            SubCommands::FromFile { .. } => return Ok(ReplaceMode::None),
+            SubCommands::ToASCII { path, .. } => {
+                return Ok(ReplaceMode::ToASCII { scope: path.scope });
+            }
+            SubCommands::Case {
+                style,
+                keep_extension,
+                path,
+                ..
+            } => {
+                return Ok(ReplaceMode::Case {
+                    transform: (*style).into(),
+                    scope: if *keep_extension {
+                        Scope::Stem
+                    } else {
+                        path.scope
+                    },
+                });
+            }
+            SubCommands::Rules {
+                rules,
+                path,
+                replace,
+                ..
+            } => return self.parse_rules(rules, path, replace),
+            SubCommands::Edit { editor, .. } => {
+                return Ok(ReplaceMode::Editor {
+                    command: editor.clone().unwrap_or_else(default_editor),
+                });
+            }
+            SubCommands::FromFile { .. }
+            | SubCommands::FromMap { .. }
+            | SubCommands::History { .. }
+            | SubCommands::Undo { .. }
+            | SubCommands::Recover { .. } => {
+                return Ok(ReplaceMode::None);
+            }
             SubCommands::Regex(regex) => regex,
         };
 
-        // Get and validate regex expression and replacement from arguments
-        let expression = match Regex::new(&regex.expression) {
-            Ok(expr) => expr,
+        let expression = self.parse_expression(&regex.expression)?;
+        let replacement = self.parse_replacement(&regex.replacement, &regex.replace)?;
+        Ok(ReplaceMode::RegExp {
+            expression,
+            replacement,
+            limit: regex.replace.replace_limit.unwrap_or(1),
+            transform: regex.replace.replace_transform.into(),
+            scope: self.default_scope(&regex.path, &regex.replace),
+        })
+    }
+
+    /// Get and validate regex expression from arguments.
+    fn parse_expression(&self, expression: &str) -> Result<Regex> {
+        match Regex::new(expression) {
+            Ok(expr) => Ok(expr),
             Err(err) => {
                 bail!(
                     "{}Bad expression provided\n\n{}",
@@ -107,29 +265,189 @@ impl ArgumentParser<'_> {
                     self.printer.colors.error.paint(err.to_string())
                 );
             }
+        }
+    }
+
+    /// Parse placeholders in the replacement.
+    fn parse_replacement(&self, replacement: &str, replace: &ReplaceArgs) -> Result<Template> {
+        match Template::parse(replacement) {
+            Ok(template) => Ok(template.with_exif_fallback(replace.exif_fallback)),
+            Err(err) => {
+                bail!(
+                    "{}Bad replacement provided\n\n{}",
+                    self.printer.colors.error.paint("Error: "),
+                    self.printer.colors.error.paint(err.message())
+                );
+            }
+        }
+    }
+
+    /// Directory of the dump files history.
+    fn history_dir(&self) -> Result<PathBuf> {
+        match history::history_dir() {
+            Some(dir) => Ok(dir),
+            None => bail!(
+                "{}Cannot find the history directory, set XDG_STATE_HOME or HOME",
+                self.printer.colors.error.paint("Error: ")
+            ),
+        }
+    }
+
+    /// Scope set in the arguments. Full path mode always replaces the path.
+    fn default_scope(&self, path: &PathArgs, replace: &ReplaceArgs) -> Scope {
+        if replace.full_path {
+            Scope::Path
+        } else {
+            path.scope
+        }
+    }
+
+    /// Read the steps of a rules file. Replacement arguments are used as default values.
+    fn parse_rules(
+        &self,
+        rules: &str,
+        path: &PathArgs,
+        replace: &ReplaceArgs,
+    ) -> Result<ReplaceMode> {
+        let bad_rules = |message: String| {
+            anyhow!(
+                "{}Bad rules file provided\n\n{}",
+                self.printer.colors.error.paint("Error: "),
+                self.printer.colors.error.paint(message)
+            )
         };
+        let content = fs::read_to_string(rules).map_err(|err| bad_rules(err.to_string()))?;
+        let rules_file: RulesFile =
+            serde_json::from_str(&content).map_err(|err| bad_rules(err.to_string()))?;
 
-        Ok(ReplaceMode::RegExp {
-            expression,
-            replacement: regex.replacement.clone(),
-            limit: regex.replace.replace_limit.unwrap_or(1),
-            transform: regex.replace.replace_transform.into(),
-        })
+        let default_scope = self.default_scope(path, replace);
+        let scope = |scope: Option<String>| match scope {
+            Some(scope) => parse_value::<Scope>(&scope).map_err(bad_rules),
+            None => Ok(default_scope),
+        };
+        let whole_text = Regex::new("(?s)^.*$").unwrap();
+        let mut steps = Vec::with_capacity(rules_file.steps.len());
+        for step in rules_file.steps {
+            let step = match step {
+                RuleStep::Regex {
+                    expression,
+                    replacement,
+                    limit,
+                    transform,
+                    scope: step_scope,
+                } => ReplaceMode::RegExp {
+                    expression: self.parse_expression(&expression)?,
+                    replacement: self.parse_replacement(&replacement, replace)?,
+                    limit: limit.or(replace.replace_limit).unwrap_or(1),
+                    transform: match transform {
+                        Some(transform) => {
+                            Some(parse_value::<ReplaceTransform>(&transform).map_err(bad_rules)?)
+                        }
+                        None => replace.replace_transform,
+                    }
+                    .into(),
+                    scope: scope(step_scope)?,
+                },
+                RuleStep::Number {
+                    replacement,
+                    scope: step_scope,
+                } => ReplaceMode::RegExp {
+                    expression: whole_text.clone(),
+                    replacement: self.parse_replacement(&replacement, replace)?,
+                    limit: 1,
+                    transform: TextTransformation::None,
+                    scope: scope(step_scope)?,
+                },
+                RuleStep::Case {
+                    style,
+                    scope: step_scope,
+                } => ReplaceMode::Case {
+                    transform: parse_value::<CaseStyle>(&style).map_err(bad_rules)?.into(),
+                    scope: scope(step_scope)?,
+                },
+                RuleStep::Ascii { scope: step_scope } => ReplaceMode::ToASCII {
+                    scope: scope(step_scope)?,
+                },
+            };
+            steps.push(step);
+        }
+
+        Ok(ReplaceMode::Rules(steps))
     }
 }
 
+/// Rules file with the steps applied in order to each path.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    steps: Vec<RuleStep>,
+}
+
+/// Step of a rules file. Values are named as in command-line arguments.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum RuleStep {
+    Regex {
+        expression: String,
+        replacement: String,
+        limit: Option<usize>,
+        transform: Option<String>,
+        scope: Option<String>,
+    },
+    /// Replace the whole text with a replacement, usually containing a counter.
+    Number {
+        replacement: String,
+        scope: Option<String>,
+    },
+    Case {
+        style: String,
+        scope: Option<String>,
+    },
+    Ascii {
+        scope: Option<String>,
+    },
+}
+
+/// Parse a command-line argument value.
+fn parse_value<T: ValueEnum>(value: &str) -> std::result::Result<T, String> {
+    T::from_str(value, false)
+}
+
 /// Parse arguments and do some checking.
 fn parse_arguments() -> Result<Config> {
     let cli = Cli::parse();
 
-    let (common, path) = match &cli.command {
-        SubCommands::Regex(RegexArgs { common, path, .. }) => (common, Some(path)),
-        SubCommands::ToASCII { common, path } => (common, Some(path)),
-        SubCommands::FromFile { common, .. } => (common, None),
+    let (common, path, replace) = match &cli.command {
+        SubCommands::Regex(RegexArgs {
+            common,
+            path,
+            replace,
+            ..
+        }) => (common, Some(path), Some(replace)),
+        SubCommands::ToASCII { common, path } => (common, Some(path), None),
+        SubCommands::Case { common, path, .. } => (common, Some(path), None),
+        SubCommands::Rules {
+            common,
+            path,
+            replace,
+            ..
+        } => (common, Some(path), Some(replace)),
+        SubCommands::Edit { common, path, .. } => (common, Some(path), None),
+        SubCommands::FromFile { common, .. } => (common, None, None),
+        SubCommands::FromMap { common, .. } => (common, None, None),
+        SubCommands::Undo { common, .. } => (common, None, None),
+        SubCommands::History { color } => return parse_history_arguments(&cli, *color),
+        SubCommands::Recover { common, .. } => (common, None, None),
     };
 
-    // Set dump defaults: write in force mode and do not in dry-run unless it is explicitly asked
-    let dump = if common.force {
+    // Confirmed operations are applied as in force mode
+    let force = common.force || common.confirm;
+
+    // Set dump defaults: write in force mode and do not in dry-run unless it is explicitly asked.
+    // Undone batches are marked in their dump file instead.
+    let dump = if matches!(cli.command, SubCommands::Undo { .. }) || common.emit_script.is_some() {
+        false
+    } else if force {
         !common.no_dump
     } else {
         common.dump
@@ -137,15 +455,21 @@ fn parse_arguments() -> Result<Config> {
 
     let printer = if common.silent {
         Printer::silent()
+    } else if common.emit_script.is_some() {
+        // Keep Stdout for the script
+        color_printer(common.color).with_stderr()
     } else {
-        match common.color {
-            crate::cli::Color::Always => Printer::color(true),
-            crate::cli::Color::NoDiff => Printer::color(false),
-            crate::cli::Color::Never => Printer::no_color(),
-            crate::cli::Color::Auto => detect_output_color(),
-        }
+        color_printer(common.color)
     };
 
+    // Answers cannot be read from a pipe or a file
+    if (common.confirm || common.interactive) && !io::stdin().is_terminal() {
+        bail!(
+            "{}Cannot ask for confirmation, standard input is not a terminal",
+            printer.colors.error.paint("Error: ")
+        );
+    }
+
     let argument_parser = ArgumentParser {
         cli: &cli,
         printer: &printer,
@@ -154,18 +478,83 @@ fn parse_arguments() -> Result<Config> {
     let run_mode = argument_parser.parse_run_mode()?;
     let replace_mode = argument_parser.parse_replace_mode()?;
 
+    // Operations read from dump and journal files are already solved
+    if common.interactive
+        && matches!(
+            run_mode,
+            RunMode::FromFile { .. } | RunMode::Undo { .. } | RunMode::Recover { .. }
+        )
+    {
+        bail!(
+            "{}Interactive mode cannot be used with operations read from a file",
+            printer.colors.error.paint("Error: ")
+        );
+    }
+
     Ok(Config {
-        force: common.force,
+        force,
+        confirm: common.confirm,
         backup: common.backup,
+        rollback: common.rollback,
+        on_change: common.on_change,
+        interactive: common.interactive,
         dirs: path.is_some_and(|p| p.include_dirs),
         dump,
-        dump_prefix: common.dump_prefix.clone(),
+        dump_prefix: common
+            .dump_prefix
+            .clone()
+            .unwrap_or_else(default_dump_prefix),
+        journal: !common.no_journal,
+        emit_script: common.emit_script,
+        full_path: replace.is_some_and(|r| r.full_path),
         run_mode,
         replace_mode,
+        number_order: replace.map_or(NumberOrder::Path, |r| r.number_order),
+        number_per_dir: replace.is_some_and(|r| r.number_per_dir),
+        printer,
+    })
+}
+
+/// Parse arguments of the history command, which does not change any file.
+fn parse_history_arguments(cli: &Cli, color: Color) -> Result<Config> {
+    let printer = color_printer(color);
+    let argument_parser = ArgumentParser {
+        cli,
+        printer: &printer,
+    };
+    let run_mode = argument_parser.parse_run_mode()?;
+
+    Ok(Config {
+        force: false,
+        confirm: false,
+        backup: false,
+        rollback: false,
+        on_change: OnChange::Refuse,
+        interactive: false,
+        dirs: false,
+        dump: false,
+        dump_prefix: default_dump_prefix(),
+        journal: false,
+        emit_script: None,
+        full_path: false,
+        run_mode,
+        replace_mode: ReplaceMode::None,
+        number_order: NumberOrder::Path,
+        number_per_dir: false,
         printer,
     })
 }
 
+/// Create a printer for the given color mode.
+fn color_printer(color: Color) -> Printer {
+    match color {
+        Color::Always => Printer::color(true),
+        Color::NoDiff => Printer::color(false),
+        Color::Never => Printer::no_color(),
+        Color::Auto => detect_output_color(),
+    }
+}
+
 /// Detect if output must be colored and returns a properly configured printer.
 fn detect_output_color() -> Printer {
     let stdout = io::stdout();
@@ -195,11 +584,112 @@ impl From<Option<ReplaceTransform>> for TextTransformation {
                 ReplaceTransform::Upper => TextTransformation::Upper,
                 ReplaceTransform::Lower => TextTransformation::Lower,
                 ReplaceTransform::Ascii => TextTransformation::Ascii,
+                ReplaceTransform::Snake => TextTransformation::Case(Case::Snake),
+                ReplaceTransform::Kebab => TextTransformation::Case(Case::Kebab),
+                ReplaceTransform::Camel => TextTransformation::Case(Case::Camel),
+                ReplaceTransform::Pascal => TextTransformation::Case(Case::Pascal),
+                ReplaceTransform::Title => TextTransformation::Case(Case::Title),
+                ReplaceTransform::Sentence => TextTransformation::Case(Case::Sentence),
             },
             None => TextTransformation::None,
         }
     }
 }
 
+impl From<CaseStyle> for TextTransformation {
+    fn from(value: CaseStyle) -> Self {
+        match value {
+            CaseStyle::Upper => TextTransformation::Upper,
+            CaseStyle::Lower => TextTransformation::Lower,
+            CaseStyle::Snake => TextTransformation::Case(Case::Snake),
+            CaseStyle::Kebab => TextTransformation::Case(Case::Kebab),
+            CaseStyle::Camel => TextTransformation::Case(Case::Camel),
+            CaseStyle::Pascal => TextTransformation::Case(Case::Pascal),
+            CaseStyle::Title => TextTransformation::Case(Case::Title),
+            CaseStyle::Sentence => TextTransformation::Case(Case::Sentence),
+        }
+    }
+}
+
 #[cfg(test)]
-mod test {}
+mod test {
+    extern crate tempfile;
+    use super::*;
+
+    /// Parse the replace mode of a rules command with the given rules file content.
+    fn parse_rules_file(content: &str, arguments: &[&str]) -> Result<ReplaceMode> {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let rules = tempdir.path().join("rules.json");
+        fs::write(&rules, content).expect("Error writing mock rules file...");
+
+        let mut args = vec!["rnr", "rules", rules.to_str().unwrap()];
+        args.extend(arguments);
+        args.push("file");
+        let cli = Cli::parse_from(args);
+        let printer = Printer::no_color();
+        let argument_parser = ArgumentParser {
+            cli: &cli,
+            printer: &printer,
+        };
+        argument_parser.parse_replace_mode()
+    }
+
+    #[test]
+    fn rules_file() {
+        let content = r#"{
+            "steps": [
+                {"type": "regex", "expression": "\\s+", "replacement": "_", "limit": 0},
+                {"type": "case", "style": "snake", "scope": "stem"},
+                {"type": "ascii"},
+                {"type": "number", "replacement": "{n:03}"}
+            ]
+        }"#;
+        let steps = match parse_rules_file(content, &["-t", "upper", "--scope", "ext"]) {
+            Ok(ReplaceMode::Rules(steps)) => steps,
+            _ => panic!("Rules file not parsed"),
+        };
+
+        assert_eq!(steps.len(), 4);
+        assert!(matches!(
+            steps[0],
+            ReplaceMode::RegExp {
+                limit: 0,
+                transform: TextTransformation::Upper,
+                scope: Scope::Ext,
+                ..
+            }
+        ));
+        assert!(matches!(
+            steps[1],
+            ReplaceMode::Case {
+                transform: TextTransformation::Case(Case::Snake),
+                scope: Scope::Stem,
+            }
+        ));
+        assert!(matches!(
+            steps[2],
+            ReplaceMode::ToASCII { scope: Scope::Ext }
+        ));
+        match &steps[3] {
+            ReplaceMode::RegExp { replacement, .. } => assert!(replacement.has_counter()),
+            _ => panic!("Number step not parsed"),
+        }
+    }
+
+    #[test]
+    fn bad_rules_file() {
+        assert!(parse_rules_file("[]", &[]).is_err());
+        assert!(parse_rules_file(r#"{"steps": [{"type": "move"}]}"#, &[]).is_err());
+        assert!(
+            parse_rules_file(r#"{"steps": [{"type": "ascii", "scope": "all"}]}"#, &[]).is_err()
+        );
+        assert!(parse_rules_file(r#"{"steps": [{"type": "ascii", "limit": 1}]}"#, &[]).is_err());
+        assert!(
+            parse_rules_file(
+                r#"{"steps": [{"type": "regex", "expression": "(", "replacement": ""}]}"#,
+                &[]
+            )
+            .is_err()
+        );
+    }
+}
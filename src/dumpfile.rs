@@ -1,41 +1,97 @@
 use crate::error::*;
-use crate::solver::{Operation, Operations};
+use crate::fileutils::{Fingerprint, create_unique_file, current_user_name, host_name};
+use crate::solver::{Operation, OperationKind, Operations};
+use path_abs::PathAbs;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-/// Dump operations intto file in JSON format
+/// Version of the dump format written by this version of the application.
+pub const DUMP_VERSION: u32 = 2;
+
+/// Dump operations intto file in JSON format. Nothing is dumped if there are no operations.
 pub fn dump_to_file(prefix: String, operations: &[Operation]) -> Result<()> {
+    if operations.is_empty() {
+        return Ok(());
+    }
     let now = chrono::Local::now();
+    let directory = env::current_dir().ok();
+    let mut operations = fingerprint_operations(operations);
+
+    // Store paths relative to a base directory, so the dump can be used from any directory
+    let absolute = |path: &Path| match PathAbs::new(path) {
+        Ok(path) => path.to_path_buf(),
+        Err(_) => directory.clone().unwrap_or_default().join(path),
+    };
+    for dumped in &mut operations {
+        dumped.operation.source = absolute(&dumped.operation.source);
+        dumped.operation.target = absolute(&dumped.operation.target);
+    }
+    let base = common_base(&operations);
+    if let Some(base) = &base {
+        for dumped in &mut operations {
+            let operation = &mut dumped.operation;
+            operation.source = operation.source.strip_prefix(base).unwrap().to_path_buf();
+            operation.target = operation.target.strip_prefix(base).unwrap().to_path_buf();
+        }
+    }
+
     let dump = DumpFormat {
+        version: DUMP_VERSION,
         date: now.format("%Y-%m-%d %H:%M:%S").to_string(),
-        operations: operations.to_vec(),
+        base,
+        directory,
+        command: Some(env::args().collect()),
+        rnr_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        host: host_name(),
+        user: current_user_name(),
+        undone: None,
+        operations,
     };
 
-    // Create filename with the following syntax: "rnr-<DATE>.json"
-    let filename = format!("{}{}{}", prefix, now.format("%Y-%m-%d_%H%M%S"), ".json");
+    // Create filename with the following syntax: "rnr-<DATE>.json". Batches dumped in the same
+    // second get a suffix instead of overwriting each other.
+    let stem = format!("{}{}", prefix, now.format("%Y-%m-%d_%H%M%S"));
+    let (path, file) = create_unique_file(&stem, ".json")?;
+    serialize_dump(file, &path.to_string_lossy(), &dump)
+}
 
-    // Dump info to a file
-    let file = match File::create(&filename) {
+/// Write a dump in a file in JSON format.
+fn write_dump(filename: &str, dump: &DumpFormat) -> Result<()> {
+    let file = match File::create(filename) {
         Ok(file) => file,
         Err(_) => {
             return Err(Error {
                 kind: ErrorKind::CreateFile,
-                value: Some(filename),
+                value: Some(filename.to_string()),
             });
         }
     };
-    match serde_json::to_writer_pretty(file, &dump) {
+    serialize_dump(file, filename, dump)
+}
+
+/// Serialize a dump into an open file.
+fn serialize_dump(file: File, filename: &str, dump: &DumpFormat) -> Result<()> {
+    match serde_json::to_writer_pretty(file, dump) {
         Ok(_) => Ok(()),
         Err(_) => Err(Error {
             kind: ErrorKind::JsonParse,
-            value: Some(filename),
+            value: Some(filename.to_string()),
         }),
     }
 }
 
-/// Read operations from a dump file and generate a Operations vector
-pub fn read_from_file(filepath: &Path) -> Result<Operations> {
+/// Mark the operations of a dump file as undone, so they are not undone twice.
+pub fn mark_undone(filepath: &Path) -> Result<()> {
+    let mut dump = read_from_file(filepath)?;
+    dump.undone = Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+    write_dump(&filepath.to_string_lossy(), &dump)
+}
+
+/// Read a dump file. Dumps written with older versions are supported.
+pub fn read_from_file(filepath: &Path) -> Result<DumpFormat> {
     let file = match File::open(filepath) {
         Ok(file) => file,
         Err(_) => {
@@ -54,11 +110,362 @@ pub fn read_from_file(filepath: &Path) -> Result<Operations> {
             });
         }
     };
-    Ok(dump.operations)
+    if dump.version > DUMP_VERSION {
+        return Err(Error {
+            kind: ErrorKind::DumpVersion,
+            value: Some(format!("{} in {}", dump.version, filepath.display())),
+        });
+    }
+    Ok(dump)
+}
+
+/// Get the fingerprint of the file moved by each operation before applying them. Files moved by a
+/// previous operation of the batch keep the fingerprint they had in their original path.
+fn fingerprint_operations(operations: &[Operation]) -> Vec<DumpOperation> {
+    let mut moved: HashMap<&Path, Option<Fingerprint>> = HashMap::new();
+    operations
+        .iter()
+        .map(|operation| {
+            let fingerprint = match operation.kind {
+                OperationKind::Rename => {
+                    let fingerprint = moved
+                        .remove(operation.source.as_path())
+                        .unwrap_or_else(|| Fingerprint::read(&operation.source));
+                    moved.insert(&operation.target, fingerprint.clone());
+                    fingerprint
+                }
+                OperationKind::Exchange => {
+                    let read = |path: &Path, moved: &mut HashMap<&Path, Option<Fingerprint>>| {
+                        moved
+                            .remove(path)
+                            .unwrap_or_else(|| Fingerprint::read(path))
+                    };
+                    let fingerprint = read(&operation.source, &mut moved);
+                    let exchanged = read(&operation.target, &mut moved);
+                    moved.insert(&operation.target, fingerprint.clone());
+                    moved.insert(&operation.source, exchanged);
+                    fingerprint
+                }
+                _ => None,
+            };
+            DumpOperation {
+                operation: operation.clone(),
+                fingerprint,
+            }
+        })
+        .collect()
+}
+
+/// Get the deepest directory containing the parents of all absolute paths in the operations.
+fn common_base(operations: &[DumpOperation]) -> Option<PathBuf> {
+    let mut paths = operations.iter().flat_map(|dumped| {
+        let operation = &dumped.operation;
+        [operation.source.parent(), operation.target.parent()]
+    });
+    let mut base = paths.next()??.to_path_buf();
+    for path in paths {
+        let path = path?;
+        while !path.starts_with(&base) {
+            base = base.parent()?.to_path_buf();
+        }
+    }
+    Some(base)
+}
+
+/// Content of a dump file. Metadata fields are missing in dumps written with the first version of
+/// the format.
+#[derive(Serialize, Deserialize)]
+pub struct DumpFormat {
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub date: String,
+    /// Directory containing all paths. Operation paths are relative to it if it is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<PathBuf>,
+    /// Working directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+    /// Command line arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rnr_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Date when the operations were undone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undone: Option<String>,
+    pub operations: Vec<DumpOperation>,
 }
 
+/// Operation with the fingerprint of the file it moves.
 #[derive(Serialize, Deserialize)]
-struct DumpFormat {
-    date: String,
-    operations: Operations,
+pub struct DumpOperation {
+    #[serde(flatten)]
+    pub operation: Operation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl DumpFormat {
+    /// Join operation paths to the base directory of the dump, or to another one to apply the
+    /// operations to a moved or copied tree. In that case, files have other inodes, so they are not
+    /// checked.
+    pub fn resolve_paths(&mut self, rebase: Option<&Path>) {
+        let Some(base) = rebase.or(self.base.as_deref()) else {
+            return;
+        };
+        for dumped in &mut self.operations {
+            let operation = &mut dumped.operation;
+            operation.source = base.join(&operation.source);
+            operation.target = base.join(&operation.target);
+            if let Some(fingerprint) = &mut dumped.fingerprint
+                && rebase.is_some()
+            {
+                fingerprint.device = None;
+                fingerprint.inode = None;
+            }
+        }
+    }
+
+    /// Operations of the dump in order.
+    pub fn operations(&self) -> Operations {
+        self.operations
+            .iter()
+            .map(|dumped| dumped.operation.clone())
+            .collect()
+    }
+
+    /// Check that the files moved by the operations were not modified or replaced since the dump
+    /// was written. Files are looked up in their targets when the operations are undone. Missing
+    /// files are not reported.
+    pub fn check_fingerprints(&self, undo: bool) -> Result<()> {
+        let mut modified = Vec::new();
+        for dumped in &self.operations {
+            let Some(fingerprint) = &dumped.fingerprint else {
+                continue;
+            };
+            let path = if undo {
+                &dumped.operation.target
+            } else {
+                &dumped.operation.source
+            };
+            if path.symlink_metadata().is_ok() && !fingerprint.matches(path) {
+                modified.push(path.display().to_string());
+            }
+        }
+
+        if modified.is_empty() {
+            Ok(())
+        } else {
+            Err(Error {
+                kind: ErrorKind::ModifiedFile,
+                value: Some(modified.join("\n")),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempfile;
+    use super::*;
+    use crate::solver;
+    use std::fs;
+
+    #[test]
+    fn read_legacy_dump() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let dumpfile = tempdir.path().join("rnr-legacy.json");
+        fs::write(
+            &dumpfile,
+            r#"{"date": "2024-01-01 10:00:00", "operations": [{"source": "a", "target": "b"}]}"#,
+        )
+        .expect("Error writing mock dump file...");
+
+        let dump = read_from_file(&dumpfile).unwrap();
+        assert_eq!(dump.version, 1);
+        assert_eq!(dump.directory, None);
+        assert_eq!(dump.operations[0].fingerprint, None);
+        let operations = dump.operations();
+        assert_eq!(operations[0].source, PathBuf::from("a"));
+        assert_eq!(operations[0].kind, OperationKind::Rename);
+
+        // Newer versions cannot be read
+        fs::write(
+            &dumpfile,
+            r#"{"version": 99, "date": "2024-01-01 10:00:00", "operations": []}"#,
+        )
+        .expect("Error writing mock dump file...");
+        let error = read_from_file(&dumpfile).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::DumpVersion);
+    }
+
+    #[test]
+    fn dump_fingerprints() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let path = |name: &str| tempdir.path().join(name);
+        fs::write(path("a.txt"), "a").expect("Error creating mock file...");
+        fs::write(path("b.txt"), "bb").expect("Error creating mock file...");
+
+        // Swap files through a temporary name
+        let rename = |source: &str, target: &str| Operation {
+            source: path(source),
+            target: path(target),
+            kind: OperationKind::Rename,
+        };
+        let operations = vec![
+            rename("a.txt", "a.tmp"),
+            rename("b.txt", "a.txt"),
+            rename("a.tmp", "b.txt"),
+        ];
+        let prefix = format!("{}/rnr-", tempdir.path().display());
+        dump_to_file(prefix, &operations).unwrap();
+        let dumpfile = fs::read_dir(tempdir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|p| p.extension().is_some_and(|e| e == "json"))
+            .unwrap();
+
+        let mut dump = read_from_file(&dumpfile).unwrap();
+        dump.resolve_paths(None);
+        assert_eq!(dump.version, DUMP_VERSION);
+        assert_eq!(dump.rnr_version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert!(dump.command.is_some());
+        let a_fingerprint = Fingerprint::read(&path("a.txt"));
+        assert_eq!(dump.operations[0].fingerprint, a_fingerprint);
+        assert_eq!(dump.operations[1].fingerprint.as_ref().unwrap().size, 2);
+        assert_eq!(dump.operations[2].fingerprint, a_fingerprint);
+        dump.check_fingerprints(false).unwrap();
+
+        // Undo detects files modified after renaming them
+        for operation in &operations {
+            operation.apply().expect("Error renaming mock file...");
+        }
+        dump.check_fingerprints(true).unwrap();
+        fs::write(path("b.txt"), "modified").expect("Error writing mock file...");
+        let error = dump.check_fingerprints(true).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ModifiedFile);
+        assert_eq!(error.value, Some(path("b.txt").display().to_string()));
+
+        // Exchanged files keep their fingerprints in the new paths
+        let exchanged = fingerprint_operations(&[
+            Operation {
+                source: path("a.txt"),
+                target: path("b.txt"),
+                kind: OperationKind::Exchange,
+            },
+            rename("b.txt", "c.txt"),
+        ]);
+        let a_fingerprint = Fingerprint::read(&path("a.txt"));
+        assert_eq!(exchanged[0].fingerprint, a_fingerprint);
+        assert_eq!(exchanged[1].fingerprint, a_fingerprint);
+    }
+
+    #[test]
+    fn dump_batches_in_same_second() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let prefix = format!("{}/rnr-", tempdir.path().display());
+        let dumps = || {
+            let mut paths: Vec<PathBuf> = fs::read_dir(tempdir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        // Empty batches are not dumped
+        dump_to_file(prefix.clone(), &[]).unwrap();
+        assert!(dumps().is_empty());
+
+        let operation = |source: &str, target: &str| Operation {
+            source: tempdir.path().join(source),
+            target: tempdir.path().join(target),
+            kind: OperationKind::Rename,
+        };
+        // Dumps written in the same second are kept in order
+        dump_to_file(prefix.clone(), &[operation("a.txt", "b.txt")]).unwrap();
+        dump_to_file(prefix.clone(), &[operation("c.txt", "d.txt")]).unwrap();
+        let paths = dumps();
+        assert_eq!(paths.len(), 2);
+        let targets: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| {
+                let mut dump = read_from_file(path).unwrap();
+                dump.resolve_paths(None);
+                dump.operations[0].operation.target.clone()
+            })
+            .collect();
+        assert_eq!(
+            targets,
+            vec![tempdir.path().join("b.txt"), tempdir.path().join("d.txt")]
+        );
+    }
+
+    #[test]
+    fn rebase_dump() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let tree = tempdir.path().join("tree");
+        fs::create_dir_all(tree.join("dir")).expect("Error creating mock dir...");
+        fs::write(tree.join("dir").join("a.txt"), "a").expect("Error creating mock file...");
+
+        let operations = vec![
+            Operation {
+                source: tree.join("dir").join("a.txt"),
+                target: tree.join("dir").join("b.txt"),
+                kind: OperationKind::Rename,
+            },
+            Operation {
+                source: tree.join("dir"),
+                target: tree.join("renamed"),
+                kind: OperationKind::Rename,
+            },
+        ];
+        let prefix = format!("{}/rnr-", tempdir.path().display());
+        dump_to_file(prefix, &operations).unwrap();
+        for operation in &operations {
+            operation.apply().expect("Error renaming mock file...");
+        }
+        let dumpfile = fs::read_dir(tempdir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|p| p.extension().is_some_and(|e| e == "json"))
+            .unwrap();
+
+        // Paths are stored relative to the common directory
+        let dump = read_from_file(&dumpfile).unwrap();
+        assert_eq!(dump.base, Some(tree.clone()));
+        assert_eq!(
+            dump.operations[0].operation.source,
+            PathBuf::from("dir").join("a.txt")
+        );
+        assert_eq!(
+            dump.operations[1].operation.target,
+            PathBuf::from("renamed")
+        );
+
+        // Undo the operations in a moved tree
+        let moved = tempdir.path().join("moved");
+        fs::rename(&tree, &moved).expect("Error moving mock dir...");
+        let mut dump = read_from_file(&dumpfile).unwrap();
+        dump.resolve_paths(Some(&moved));
+        dump.check_fingerprints(true).unwrap();
+        let reverted = solver::revert_operations(&dump.operations()).unwrap();
+        solver::validate_operations(&reverted).unwrap();
+        for operation in &reverted {
+            operation
+                .apply()
+                .expect("Error reverting mock operation...");
+        }
+        assert!(moved.join("dir").join("a.txt").exists());
+    }
 }
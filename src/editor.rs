@@ -0,0 +1,107 @@
+use crate::error::*;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// Get the editor command from `VISUAL` or `EDITOR` environment variables.
+pub fn default_editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        })
+}
+
+/// Write the lines into a temporary file, open it with the editor command and return the edited
+/// lines. The command can contain arguments, and the file path is appended to them.
+pub fn edit_lines(command: &str, lines: &[&str]) -> Result<Vec<String>> {
+    let editor_error = |message: String| Error {
+        kind: ErrorKind::Editor,
+        value: Some(message),
+    };
+
+    let mut file = tempfile::Builder::new()
+        .prefix("rnr-")
+        .suffix(".txt")
+        .tempfile()
+        .map_err(|err| editor_error(err.to_string()))?;
+    for line in lines {
+        writeln!(file, "{}", line).map_err(|err| editor_error(err.to_string()))?;
+    }
+    file.flush().map_err(|err| editor_error(err.to_string()))?;
+
+    if command.trim().is_empty() {
+        return Err(editor_error("empty command".to_string()));
+    }
+    let status = editor_command(command, file.path())
+        .status()
+        .map_err(|err| editor_error(format!("{}: {}", command, err)))?;
+    if !status.success() {
+        return Err(editor_error(format!("{}: {}", command, status)));
+    }
+
+    // Editors may replace the file, so it is read again from its path
+    let content = fs::read_to_string(file.path()).map_err(|_| Error {
+        kind: ErrorKind::ReadFile,
+        value: Some(file.path().to_string_lossy().to_string()),
+    })?;
+    Ok(content.lines().map(String::from).collect())
+}
+
+/// Build the editor command. Unix shells parse it like git does, so paths and arguments can be
+/// quoted, and the file path is passed as the last argument.
+#[cfg(unix)]
+fn editor_command(command: &str, path: &Path) -> Command {
+    let mut editor = Command::new("sh");
+    editor
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg(command)
+        .arg(path);
+    editor
+}
+
+/// Build the editor command splitting its arguments by whitespace.
+#[cfg(not(unix))]
+fn editor_command(command: &str, path: &Path) -> Command {
+    let mut arguments = command.split_whitespace();
+    let mut editor = Command::new(arguments.next().unwrap_or_default());
+    editor.args(arguments).arg(path);
+    editor
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn edit_lines_with_command() {
+        let lines = ["a.txt", "b.txt"];
+        let edited = edit_lines("sed -i s/txt/md/", &lines).unwrap();
+        assert_eq!(edited, vec!["a.md", "b.md"]);
+
+        let error = edit_lines("false", &lines).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Editor);
+        let error = edit_lines("rnr-missing-editor", &lines).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Editor);
+
+        // Editor paths and arguments can be quoted
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let script = tempdir.path().join("my editor.sh");
+        fs::write(&script, "#!/bin/sh\nsed -i \"s/$1/$2/\" \"$3\"\n").unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let command = format!("'{}' txt 'new md'", script.display());
+        let edited = edit_lines(&command, &lines).unwrap();
+        assert_eq!(edited, vec!["a.new md", "b.new md"]);
+    }
+}
@@ -14,30 +14,81 @@ pub struct Error {
 /// Define type of error
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ErrorKind {
+    BadPlaceholder,
+    ChangeDirectory,
+    ChangedSource,
     CreateBackup,
+    CreateDir,
     CreateFile,
     CreateSymlink,
+    DumpVersion,
+    EditedList,
+    Editor,
     ExistingPath,
+    InterruptedExchange,
     JsonParse,
+    MapParse,
+    MissingBatch,
+    MissingSource,
+    MissingValue,
+    ModifiedFile,
+    Preflight,
     ReadFile,
+    RemoveDir,
+    RemoveFile,
     Rename,
+    Rollback,
+    RollbackFailed,
     SameFilename,
     SolveOrder,
+    TargetCreated,
+    UndoneBatch,
+    WriteFile,
 }
 
 impl Error {
     pub fn description(&self) -> &str {
         use self::ErrorKind::*;
         match self.kind {
+            BadPlaceholder => "Bad placeholder in replacement ",
+            ChangeDirectory => "Cannot move to another directory ",
+            ChangedSource => "Sources were modified or replaced after solving the operations\n",
             CreateBackup => "Cannot create a backup of ",
+            CreateDir => "Cannot create directory ",
             CreateFile => "Cannot create file ",
             CreateSymlink => "Cannot create symlink ",
+            DumpVersion => "Unsupported dump file version ",
+            EditedList => "Bad edited list of paths, ",
+            Editor => "Cannot edit the list of paths with ",
             ExistingPath => "Conflict with existing path ",
+            InterruptedExchange => "Cannot know if the interrupted exchange was applied ",
             JsonParse => "Cannot parse JSON file ",
+            MapParse => "Cannot parse mapping file ",
+            MissingBatch => "Cannot find batch in history ",
+            MissingSource => "Source path does not exist ",
+            MissingValue => "Missing value for placeholder ",
+            ModifiedFile => "Files were modified or replaced after the dump was written\n",
+            Preflight => "Operations do not match the current files\n",
             ReadFile => "Cannot open/read file ",
+            RemoveDir => "Cannot remove directory ",
+            RemoveFile => "Cannot remove file ",
             Rename => "Cannot rename ",
+            Rollback => "Rename failed, applied operations were rolled back\n",
+            RollbackFailed => "Rename failed and applied operations could not be rolled back\n",
             SameFilename => "Files will have the same name\n",
             SolveOrder => "Cannot solve sorting problem.",
+            TargetCreated => "Target was created after solving the operations ",
+            UndoneBatch => "Batch was already undone ",
+            WriteFile => "Cannot write file ",
         }
     }
+
+    /// Return the full error message without any formatting.
+    pub fn message(&self) -> String {
+        format!(
+            "{}{}",
+            self.description(),
+            self.value.as_deref().unwrap_or_default()
+        )
+    }
 }
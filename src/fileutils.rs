@@ -1,9 +1,15 @@
 use crate::config::RunMode;
 use crate::error::*;
 use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fs;
+use std::io;
+use std::iter::Peekable;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::Chars;
+use std::time::SystemTime;
 use walkdir::{DirEntry, WalkDir};
 
 pub type PathList = Vec<PathBuf>;
@@ -102,6 +108,339 @@ pub fn cleanup_paths(paths: PathList, keep_dirs: bool) -> PathList {
     paths
 }
 
+/// Compare strings in natural order, so digit sequences are compared by their numeric value (e.g.
+/// `file-2` < `file-10`).
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        let ordering = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_number = take_digits(&mut a_chars);
+                let b_number = take_digits(&mut b_chars);
+                let a_value = a_number.trim_start_matches('0');
+                let b_value = b_number.trim_start_matches('0');
+                a_value
+                    .len()
+                    .cmp(&b_value.len())
+                    .then_with(|| a_value.cmp(b_value))
+                    .then_with(|| a_number.len().cmp(&b_number.len()))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                a_chars.next();
+                b_chars.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Consume consecutive digits from the iterator.
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Split a file name into its stem and extension, without the dot. Leading dots are part of the
+/// stem, so hidden files without extension do not have one. Compressed tarballs have a two part
+/// extension, e.g. `tar.gz`.
+pub fn split_extension(file_name: &str) -> (&str, Option<&str>) {
+    let name = file_name.trim_start_matches('.');
+    let dots = file_name.len() - name.len();
+    let position = match name.rfind('.') {
+        Some(position) if position > 0 && position < name.len() - 1 => position,
+        _ => return (file_name, None),
+    };
+
+    let position = match name[..position].rfind('.') {
+        Some(tar) if tar > 0 && name[tar + 1..position].eq_ignore_ascii_case("tar") => tar,
+        _ => position,
+    };
+    (
+        &file_name[..dots + position],
+        Some(&file_name[dots + position + 1..]),
+    )
+}
+
+/// Format a size in bytes in a human readable way using binary units (e.g. `1.5K`).
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+/// Get the name of the user with the given id.
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: all pointers are valid during the call and the buffer length is correct. The name is
+    // only read if the entry was found.
+    unsafe {
+        let mut passwd: libc::passwd = std::mem::zeroed();
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        let code = libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        );
+        if code != 0 || result.is_null() {
+            return None;
+        }
+        Some(
+            std::ffi::CStr::from_ptr(passwd.pw_name)
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+}
+
+/// Get the name of the group with the given id.
+#[cfg(unix)]
+pub fn group_name(gid: u32) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: all pointers are valid during the call and the buffer length is correct. The name is
+    // only read if the entry was found.
+    unsafe {
+        let mut group: libc::group = std::mem::zeroed();
+        let mut result: *mut libc::group = std::ptr::null_mut();
+        let code = libc::getgrgid_r(
+            gid,
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        );
+        if code != 0 || result.is_null() {
+            return None;
+        }
+        Some(
+            std::ffi::CStr::from_ptr(group.gr_name)
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+}
+
+/// Get the name of this host.
+pub fn host_name() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buffer = vec![0 as libc::c_char; 256];
+        // SAFETY: the buffer length is correct and it is terminated with a null character in case
+        // the name was truncated.
+        unsafe {
+            if libc::gethostname(buffer.as_mut_ptr(), buffer.len() - 1) != 0 {
+                return None;
+            }
+            Some(
+                std::ffi::CStr::from_ptr(buffer.as_ptr())
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME").ok()
+    }
+}
+
+/// Get the name of the user running the process.
+pub fn current_user_name() -> Option<String> {
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid always succeeds.
+        user_name(unsafe { libc::getuid() })
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("USERNAME").ok()
+    }
+}
+
+/// Type of a file system entry.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// File metadata used to detect if a file was modified or replaced by another one. Device and inode
+/// are only available in Unix systems.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    #[serde(rename = "type")]
+    pub kind: FileKind,
+    pub size: u64,
+    pub mtime: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+}
+
+impl Fingerprint {
+    /// Read the fingerprint of the given path without following symlinks.
+    pub fn read(path: &Path) -> Option<Fingerprint> {
+        let metadata = path.symlink_metadata().ok()?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        };
+        #[cfg(unix)]
+        let (device, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            (Some(metadata.dev()), Some(metadata.ino()))
+        };
+        #[cfg(not(unix))]
+        let (device, inode) = (None, None);
+
+        Some(Fingerprint {
+            kind,
+            size: metadata.len(),
+            mtime: metadata.modified().ok(),
+            device,
+            inode,
+        })
+    }
+
+    /// Check if the file in the given path is the same one and it was not modified. Size and
+    /// modification time of directories change with their content, so they are not compared.
+    pub fn matches(&self, path: &Path) -> bool {
+        let Some(current) = Fingerprint::read(path) else {
+            return false;
+        };
+        // Dumps written in other systems may not have device and inode
+        current.kind == self.kind
+            && (self.device.is_none() || current.device == self.device)
+            && (self.inode.is_none() || current.inode == self.inode)
+            && (self.kind == FileKind::Dir
+                || (current.size == self.size && current.mtime == self.mtime))
+    }
+}
+
+/// Create the parent directory of the given path and its ancestors if they do not exist.
+pub fn create_parent_dir(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            fs::create_dir_all(parent).map_err(|_| Error {
+                kind: ErrorKind::CreateDir,
+                value: Some(parent.to_string_lossy().to_string()),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Create a new file named `<stem><extension>`, creating its parent directory if required. When
+/// the file exists, a `_<N>` suffix is added to the stem, so existing files are never truncated.
+pub fn create_unique_file(stem: &str, extension: &str) -> Result<(PathBuf, fs::File)> {
+    let mut path = PathBuf::from(format!("{}{}", stem, extension));
+    create_parent_dir(&path)?;
+
+    let mut index = 0;
+    loop {
+        match fs::File::create_new(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                index += 1;
+                path = PathBuf::from(format!("{}_{}{}", stem, index, extension));
+            }
+            Err(_) => {
+                return Err(Error {
+                    kind: ErrorKind::CreateFile,
+                    value: Some(path.to_string_lossy().to_string()),
+                });
+            }
+        }
+    }
+}
+
+/// Rename a path failing with `AlreadyExists` if the target exists. Linux checks it atomically
+/// with `renameat2`, other systems and file systems without support check it before renaming.
+/// Targets referencing the same file as the source, like in case-insensitive file systems, are
+/// renamed anyway.
+pub fn rename_no_replace(source: &Path, target: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    match renameat2(source, target, libc::RENAME_NOREPLACE) {
+        Err(err)
+            if err.raw_os_error() == Some(libc::EEXIST) && same_existing_file(source, target) =>
+        {
+            return fs::rename(source, target);
+        }
+        // Unsupported by the kernel or the file system
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) => {}
+        result => return result,
+    }
+
+    if target.symlink_metadata().is_ok() && !same_existing_file(source, target) {
+        return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+    }
+    fs::rename(source, target)
+}
+
+/// Check if both paths exist and reference the same file.
+fn same_existing_file(source: &Path, target: &Path) -> bool {
+    source.symlink_metadata().is_ok()
+        && target.symlink_metadata().is_ok()
+        && is_same_file(source, target)
+}
+
+/// Call `renameat2` with the given flags relative to the working directory.
+#[cfg(target_os = "linux")]
+pub fn renameat2(source: &Path, target: &Path, flags: libc::c_uint) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid null terminated strings during the call. The system call is
+    // used directly because not every libc provides a wrapper.
+    let code = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            source.as_ptr(),
+            libc::AT_FDCWD,
+            target.as_ptr(),
+            flags,
+        )
+    };
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
 /// Wrapper to create symlink files without considering the OS explicitly
 #[allow(dead_code)]
 pub fn create_symlink(source: &Path, symlink_file: &Path) -> Result<()> {
@@ -222,6 +561,51 @@ mod test {
         assert!(files.contains(&PathBuf::from("test_file_3.txt")));
     }
 
+    #[test]
+    fn natural_order() {
+        let mut names = vec![
+            "file-10.txt",
+            "file-2.txt",
+            "file-02.txt",
+            "file-1.txt",
+            "file.txt",
+            "File-3.txt",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "File-3.txt",
+                "file-1.txt",
+                "file-2.txt",
+                "file-02.txt",
+                "file-10.txt",
+                "file.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn file_extension() {
+        assert_eq!(split_extension("file.txt"), ("file", Some("txt")));
+        assert_eq!(split_extension("file.v2.txt"), ("file.v2", Some("txt")));
+        assert_eq!(split_extension("backup.TAR.gz"), ("backup", Some("TAR.gz")));
+        assert_eq!(split_extension("tar.gz"), ("tar", Some("gz")));
+        assert_eq!(split_extension(".bashrc"), (".bashrc", None));
+        assert_eq!(split_extension("..hidden.txt"), ("..hidden", Some("txt")));
+        assert_eq!(split_extension("file."), ("file.", None));
+        assert_eq!(split_extension("file"), ("file", None));
+    }
+
+    #[test]
+    fn human_readable_size() {
+        assert_eq!(human_size(0), "0B");
+        assert_eq!(human_size(1023), "1023B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(10 * 1024 * 1024), "10.0M");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0G");
+    }
+
     #[test]
     fn test_create_symlinks() {
         let tempdir = tempfile::tempdir().expect("Error creating temp directory");
@@ -567,4 +951,36 @@ mod test {
             assert!(!mock_paths.contains(file));
         }
     }
+
+    #[test]
+    fn create_unique_files() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let stem = format!("{}/dir/rnr-2024", tempdir.path().display());
+        let (first, _) = create_unique_file(&stem, ".json").unwrap();
+        fs::write(&first, "first").expect("Error writing mock file...");
+        let (second, _) = create_unique_file(&stem, ".json").unwrap();
+        let (third, _) = create_unique_file(&stem, ".json").unwrap();
+        assert_eq!(first, PathBuf::from(format!("{}.json", stem)));
+        assert_eq!(second, PathBuf::from(format!("{}_1.json", stem)));
+        assert_eq!(third, PathBuf::from(format!("{}_2.json", stem)));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first");
+    }
+
+    #[test]
+    fn rename_without_replacing() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let path = |name: &str| tempdir.path().join(name);
+        fs::write(path("a.txt"), "a").expect("Error creating mock file...");
+        fs::write(path("b.txt"), "b").expect("Error creating mock file...");
+
+        let error = rename_no_replace(&path("a.txt"), &path("b.txt")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(path("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(path("b.txt")).unwrap(), "b");
+
+        rename_no_replace(&path("a.txt"), &path("c.txt")).unwrap();
+        assert!(!path("a.txt").exists());
+        assert_eq!(fs::read_to_string(path("c.txt")).unwrap(), "a");
+    }
 }
@@ -0,0 +1,231 @@
+use crate::dumpfile::{self, DumpFormat};
+use crate::error::*;
+use crate::output::Printer;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Batch of operations stored in the history directory. Identifiers are assigned from the oldest
+/// batch to the newest one starting at 1.
+pub struct Batch {
+    pub id: usize,
+    pub path: PathBuf,
+    pub dump: DumpFormat,
+}
+
+/// Directory where dump files are stored by default: `$XDG_STATE_HOME/rnr` or
+/// `~/.local/state/rnr` in Unix systems and `%LOCALAPPDATA%\rnr` in Windows.
+pub fn history_dir() -> Option<PathBuf> {
+    let absolute = |var: &str| {
+        env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+    let state_dir = if cfg!(windows) {
+        absolute("LOCALAPPDATA")
+    } else {
+        absolute("XDG_STATE_HOME")
+            .or_else(|| absolute("HOME").map(|home| home.join(".local").join("state")))
+    };
+    state_dir.map(|dir| dir.join("rnr"))
+}
+
+/// Default prefix of dump and journal files in the history directory. Files are written in the
+/// working directory if it is not available.
+pub fn default_dump_prefix() -> String {
+    match history_dir() {
+        Some(dir) => dir.join("rnr-").to_string_lossy().to_string(),
+        None => "rnr-".to_string(),
+    }
+}
+
+/// Read all the batches of the history directory sorted by date. Files that are not valid dumps
+/// are ignored.
+pub fn read_history(dir: &Path) -> Result<Vec<Batch>> {
+    // The history may not exist yet
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            return Err(Error {
+                kind: ErrorKind::ReadFile,
+                value: Some(dir.to_string_lossy().to_string()),
+            });
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("rnr-") && name.ends_with(".json")
+        })
+        .collect();
+    // Dump file names contain the date, and a number when dumped in the same second
+    paths.sort_by_cached_key(|path| history_order(path));
+
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| Some((dumpfile::read_from_file(&path).ok()?, path)))
+        .enumerate()
+        .map(|(index, (dump, path))| Batch {
+            id: index + 1,
+            path,
+            dump,
+        })
+        .collect())
+}
+
+/// Get the sorting key of a dump file, splitting the number added to batches dumped in the same
+/// second from the date, so `rnr-<DATE>_10.json` goes after `rnr-<DATE>_2.json`.
+fn history_order(path: &Path) -> (String, usize) {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    // The date already contains an underscore between the day and the time
+    if let Some((date, number)) = stem.rsplit_once('_')
+        && date.contains('_')
+        && let Ok(number) = number.parse()
+    {
+        return (date.to_string(), number);
+    }
+    (stem.to_string(), 0)
+}
+
+/// Find a batch by its identifier, or the last one that was not undone. Batches without
+/// operations are not considered as the last one.
+pub fn find_batch(dir: &Path, id: Option<usize>) -> Result<Batch> {
+    let batches = read_history(dir)?;
+    let batch = match id {
+        Some(id) => batches.into_iter().find(|batch| batch.id == id),
+        None => batches
+            .into_iter()
+            .rev()
+            .find(|batch| batch.dump.undone.is_none() && !batch.dump.operations.is_empty()),
+    };
+    match batch {
+        Some(batch) if batch.dump.undone.is_some() => Err(Error {
+            kind: ErrorKind::UndoneBatch,
+            value: Some(format!("{} on {}", batch.id, batch.dump.undone.unwrap())),
+        }),
+        Some(batch) => Ok(batch),
+        None => Err(Error {
+            kind: ErrorKind::MissingBatch,
+            value: Some(match id {
+                Some(id) => id.to_string(),
+                None => "to undo".to_string(),
+            }),
+        }),
+    }
+}
+
+/// Print a line for each batch with its identifier, date, number of operations, working directory
+/// and command.
+pub fn print_history(printer: &Printer, batches: &[Batch]) {
+    let colors = &printer.colors;
+    for batch in batches {
+        let dump = &batch.dump;
+        let directory = dump
+            .directory
+            .as_ref()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let command = dump
+            .command
+            .as_ref()
+            .map(|args| args.join(" "))
+            .unwrap_or_else(|| "-".to_string());
+        let undone = match &dump.undone {
+            Some(date) => colors
+                .warn
+                .paint(format!(" (undone on {})", date))
+                .to_string(),
+            None => String::new(),
+        };
+        printer.print(&format!(
+            "{}  {}  {:>5} ops  {}  {}{}",
+            colors.info.paint(format!("{:>4}", batch.id)),
+            dump.date,
+            dump.operations.len(),
+            directory,
+            command,
+            undone
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempfile;
+    use super::*;
+
+    #[test]
+    fn find_batches() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let dir = tempdir.path();
+        assert!(read_history(&dir.join("missing")).unwrap().is_empty());
+
+        let dump = |date: &str, undone: &str| {
+            format!(
+                r#"{{"date": "{}", "operations": [{{"source": "a", "target": "b"}}]{}}}"#,
+                date,
+                match undone {
+                    "" => String::new(),
+                    date => format!(r#", "undone": "{}""#, date),
+                }
+            )
+        };
+        let write = |name: &str, content: String| {
+            fs::write(dir.join(name), content).expect("Error writing mock dump file...")
+        };
+        write(
+            "rnr-2024-01-02_100000.json",
+            dump("2024-01-02 10:00:00", ""),
+        );
+        write(
+            "rnr-2024-01-03_100000.json",
+            dump("2024-01-03 10:00:00", "2024-01-04 10:00:00"),
+        );
+        write(
+            "rnr-2024-01-01_100000.json",
+            dump("2024-01-01 10:00:00", ""),
+        );
+        write("rnr-2024-01-05_100000.json", "invalid".to_string());
+        // Batches dumped in the same second are sorted by their number
+        for number in [10, 2] {
+            write(
+                &format!("rnr-2024-01-05_100000_{}.json", number),
+                dump(
+                    &format!("2024-01-05 10:00:{:02}", number),
+                    "2024-01-05 11:00:00",
+                ),
+            );
+        }
+        write(
+            "rnr-2024-01-06_100000.json",
+            r#"{"date": "2024-01-06 10:00:00", "operations": []}"#.to_string(),
+        );
+        write("other.json", dump("2024-01-06 10:00:00", ""));
+
+        let batches = read_history(dir).unwrap();
+        let dates: Vec<&str> = batches.iter().map(|b| b.dump.date.as_str()).collect();
+        assert_eq!(
+            dates,
+            vec![
+                "2024-01-01 10:00:00",
+                "2024-01-02 10:00:00",
+                "2024-01-03 10:00:00",
+                "2024-01-05 10:00:02",
+                "2024-01-05 10:00:10",
+                "2024-01-06 10:00:00"
+            ]
+        );
+
+        // The last batch was already undone and the empty one is ignored
+        assert_eq!(find_batch(dir, None).unwrap().id, 2);
+        assert_eq!(find_batch(dir, Some(1)).unwrap().id, 1);
+        let error = find_batch(dir, Some(3)).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::UndoneBatch);
+        let error = find_batch(dir, Some(7)).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::MissingBatch);
+    }
+}
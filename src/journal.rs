@@ -0,0 +1,343 @@
+use crate::error::*;
+use crate::fileutils::{Fingerprint, create_unique_file};
+use crate::solver::{Operation, OperationKind, Operations};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Write-ahead journal that tracks the progress of a batch of operations. The first line stores
+/// all the operations of the batch and each following line records a state change of one of them,
+/// so an interrupted batch can be completed or rolled back later.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    operations: Operations,
+}
+
+/// State of a single operation in the journal
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationState {
+    Pending,
+    Done,
+    Reverting,
+    Reverted,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalHeader {
+    operations: Operations,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    index: usize,
+    state: OperationState,
+    /// Fingerprint of the file moved to the target by an exchange, recorded before applying it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<Fingerprint>,
+}
+
+impl Journal {
+    /// Create a journal file for the given operations with the following syntax:
+    /// "<PREFIX><DATE>.journal", adding a suffix if another batch used the same name.
+    pub fn create(prefix: &str, operations: &[Operation]) -> Result<Journal> {
+        let now = chrono::Local::now();
+        let stem = format!("{}{}", prefix, now.format("%Y-%m-%d_%H%M%S"));
+        let (path, file) = create_unique_file(&stem, ".journal")?;
+        let mut journal = Journal {
+            path,
+            file,
+            operations: operations.to_vec(),
+        };
+
+        let header = JournalHeader {
+            operations: journal.operations.clone(),
+        };
+        journal.write_line(&serde_json::to_string(&header))?;
+        Ok(journal)
+    }
+
+    /// Path of the journal file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record a new state for the operation in the given index. Exchanges that are going to be
+    /// applied or reverted record the fingerprint of the file moved to their target, since both
+    /// paths exist before and after swapping them.
+    pub fn record(&mut self, index: usize, state: OperationState) -> Result<()> {
+        let operation = &self.operations[index];
+        let fingerprint = match (operation.kind, state) {
+            (OperationKind::Exchange, OperationState::Pending) => {
+                Fingerprint::read(&operation.source)
+            }
+            (OperationKind::Exchange, OperationState::Reverting) => {
+                Fingerprint::read(&operation.target)
+            }
+            _ => None,
+        };
+        self.write_line(&serde_json::to_string(&JournalEntry {
+            index,
+            state,
+            fingerprint,
+        }))
+    }
+
+    /// Remove the journal file once the batch is finished
+    pub fn finish(self) -> Result<()> {
+        remove_journal(&self.path)
+    }
+
+    /// Write a line in the journal file. Each line is written at once without buffering to keep
+    /// the journal up to date if the process is killed.
+    fn write_line(&mut self, line: &serde_json::Result<String>) -> Result<()> {
+        let result = match line {
+            Ok(line) => self.file.write_all(format!("{}\n", line).as_bytes()),
+            Err(_) => {
+                return Err(Error {
+                    kind: ErrorKind::JsonParse,
+                    value: Some(self.path.to_string_lossy().to_string()),
+                });
+            }
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error {
+                kind: ErrorKind::WriteFile,
+                value: Some(self.path.to_string_lossy().to_string()),
+            }),
+        }
+    }
+}
+
+/// Read a journal file and return its operations and the number of them that were applied. If the
+/// last recorded operation was interrupted, the filesystem is inspected to know if it took place.
+pub fn read_journal(path: &Path) -> Result<(Operations, usize)> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => {
+            return Err(Error {
+                kind: ErrorKind::ReadFile,
+                value: Some(path.to_string_lossy().to_string()),
+            });
+        }
+    };
+    let json_error = || Error {
+        kind: ErrorKind::JsonParse,
+        value: Some(path.to_string_lossy().to_string()),
+    };
+
+    let mut lines = BufReader::new(file).lines().map_while(|l| l.ok());
+    let header: JournalHeader = match lines.next().map(|l| serde_json::from_str(&l)) {
+        Some(Ok(header)) => header,
+        _ => return Err(json_error()),
+    };
+    let operations = header.operations;
+
+    // Replay journal entries. The last line may be truncated if the process was killed while
+    // writing it, so parsing stops at the first invalid entry.
+    let mut applied = 0;
+    let mut last_entry: Option<JournalEntry> = None;
+    for line in lines {
+        let entry: JournalEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(_) => break,
+        };
+        if entry.index >= operations.len() {
+            return Err(json_error());
+        }
+        match entry.state {
+            OperationState::Done => applied = entry.index + 1,
+            OperationState::Reverted => applied = entry.index,
+            _ => {}
+        }
+        last_entry = Some(entry);
+    }
+
+    // Inspect the filesystem to check interrupted operations
+    match last_entry {
+        Some(JournalEntry {
+            index,
+            state: OperationState::Pending,
+            fingerprint,
+        }) if is_applied(&operations[index], fingerprint.as_ref())? => applied = index + 1,
+        Some(JournalEntry {
+            index,
+            state: OperationState::Reverting,
+            fingerprint,
+        }) if is_applied(&operations[index].revert(), fingerprint.as_ref())? => applied = index,
+        _ => {}
+    }
+
+    Ok((operations, applied))
+}
+
+/// Check if an interrupted operation was applied. Exchanges are checked finding the recorded file
+/// in their target, failing if it cannot be known.
+fn is_applied(operation: &Operation, fingerprint: Option<&Fingerprint>) -> Result<bool> {
+    if operation.kind != OperationKind::Exchange {
+        return Ok(operation.is_applied());
+    }
+    let moved = fingerprint.map(|f| (f.matches(&operation.source), f.matches(&operation.target)));
+    match moved {
+        Some((false, true)) => Ok(true),
+        Some((true, false)) => Ok(false),
+        _ => Err(Error {
+            kind: ErrorKind::InterruptedExchange,
+            value: Some(format!(
+                "{} <-> {}",
+                operation.source.display(),
+                operation.target.display()
+            )),
+        }),
+    }
+}
+
+/// Remove a journal file
+pub fn remove_journal(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error {
+            kind: ErrorKind::RemoveFile,
+            value: Some(path.to_string_lossy().to_string()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempfile;
+    use super::*;
+    use crate::fileutils::PathList;
+    use crate::solver::OperationKind;
+
+    /// Generate mock operations in the given directory: `a -> b`, `c -> d` and `e -> f`.
+    fn mock_operations(temp_path: &str) -> Operations {
+        let names = ["a", "b", "c", "d", "e", "f"];
+        let paths: PathList = names
+            .iter()
+            .map(|n| [temp_path, n].iter().collect())
+            .collect();
+        paths
+            .chunks(2)
+            .map(|p| Operation {
+                source: p[0].clone(),
+                target: p[1].clone(),
+                kind: OperationKind::Rename,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn journal_progress() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let prefix = format!("{}/rnr-", temp_path);
+        let operations = mock_operations(temp_path);
+
+        let mut journal = Journal::create(&prefix, &operations).expect("Error creating journal.");
+        let journal_path = journal.path().to_path_buf();
+        journal.record(0, OperationState::Pending).unwrap();
+        journal.record(0, OperationState::Done).unwrap();
+        journal.record(1, OperationState::Pending).unwrap();
+        journal.record(1, OperationState::Done).unwrap();
+
+        let (read_operations, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(read_operations.len(), operations.len());
+        assert_eq!(applied, 2);
+
+        // Rollback of the last operation
+        journal.record(1, OperationState::Reverting).unwrap();
+        journal.record(1, OperationState::Reverted).unwrap();
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 1);
+
+        journal.finish().unwrap();
+        assert!(!journal_path.exists());
+    }
+
+    #[test]
+    fn journal_interrupted_operation() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let prefix = format!("{}/rnr-", temp_path);
+        let operations = mock_operations(temp_path);
+
+        let mut journal = Journal::create(&prefix, &operations).expect("Error creating journal.");
+        let journal_path = journal.path().to_path_buf();
+        journal.record(0, OperationState::Pending).unwrap();
+        journal.record(0, OperationState::Done).unwrap();
+        journal.record(1, OperationState::Pending).unwrap();
+
+        // Operation was not performed in the filesystem
+        fs::File::create(&operations[1].source).expect("Error creating mock file...");
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 1);
+
+        // Operation was performed but not recorded
+        fs::rename(&operations[1].source, &operations[1].target).unwrap();
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 2);
+
+        // Truncated entries are ignored
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&journal_path)
+            .unwrap();
+        file.write_all(b"{\"index\":2,\"sta").unwrap();
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 2);
+    }
+
+    #[test]
+    fn journal_interrupted_exchange() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let prefix = format!("{}/rnr-", temp_path);
+        let operation = Operation {
+            source: tempdir.path().join("a"),
+            target: tempdir.path().join("b"),
+            kind: OperationKind::Exchange,
+        };
+        fs::write(&operation.source, "a").expect("Error creating mock file...");
+        fs::write(&operation.target, "bb").expect("Error creating mock file...");
+
+        let mut journal = Journal::create(&prefix, std::slice::from_ref(&operation))
+            .expect("Error creating journal.");
+        let journal_path = journal.path().to_path_buf();
+        journal.record(0, OperationState::Pending).unwrap();
+
+        // Exchange was not performed in the filesystem
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 0);
+
+        // Exchange was performed but not recorded
+        operation.apply().expect("Error exchanging mock files...");
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 1);
+
+        // Rollback was interrupted after swapping the files back
+        journal.record(0, OperationState::Done).unwrap();
+        journal.record(0, OperationState::Reverting).unwrap();
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 1);
+        operation.apply().expect("Error exchanging mock files...");
+        let (_, applied) = read_journal(&journal_path).unwrap();
+        assert_eq!(applied, 0);
+
+        // The state is unknown without the fingerprint
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&journal_path)
+            .unwrap();
+        file.write_all(b"{\"index\":0,\"state\":\"pending\"}\n")
+            .unwrap();
+        let error = read_journal(&journal_path).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InterruptedExchange);
+    }
+}
@@ -12,19 +12,30 @@ extern crate serde;
 extern crate serde_json;
 extern crate walkdir;
 
+#[cfg(unix)]
+extern crate libc;
+
 extern crate clap;
 extern crate serde_derive;
 
 use crate::renamer::Renamer;
 
+mod case;
 mod cli;
 mod config;
 mod dumpfile;
+mod editor;
 mod error;
 mod fileutils;
+mod history;
+mod journal;
+mod mapfile;
 mod output;
+mod prompt;
 mod renamer;
+mod script;
 mod solver;
+mod template;
 
 fn main() {
     // Read arguments
@@ -36,7 +47,10 @@ fn main() {
         }
     };
 
-    if !config.force {
+    if !config.force
+        && config.emit_script.is_none()
+        && !matches!(config.run_mode, config::RunMode::History { .. })
+    {
         let info = &config.printer.colors.info;
         config
             .printer
@@ -61,6 +75,24 @@ fn main() {
         }
     };
 
+    // Print the operations as a script instead of applying them
+    if let Some(shell) = config.emit_script {
+        print!("{}", script::write_script(shell, &operations));
+        return;
+    }
+
+    // Show the operations and apply them once they are confirmed
+    if config.confirm {
+        match renamer.confirm_operations(&operations) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                config.printer.print_error(&err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Batch rename operations
     if let Err(err) = renamer.batch_rename(operations) {
         config.printer.print_error(&err);
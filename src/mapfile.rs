@@ -0,0 +1,111 @@
+use crate::error::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Read source and target paths from a two-column CSV/TSV mapping file. Rows where the source
+/// and the target are the same are ignored.
+pub fn read_mapping(
+    filepath: &Path,
+    delimiter: u8,
+    header: bool,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut reader = match csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(header)
+        .flexible(true)
+        .from_path(filepath)
+    {
+        Ok(reader) => reader,
+        Err(_) => {
+            return Err(Error {
+                kind: ErrorKind::ReadFile,
+                value: Some(filepath.to_string_lossy().to_string()),
+            });
+        }
+    };
+    let parse_error = |line: Option<u64>, message: String| Error {
+        kind: ErrorKind::MapParse,
+        value: Some(match line {
+            Some(line) => format!("{}:{}: {}", filepath.display(), line, message),
+            None => format!("{}: {}", filepath.display(), message),
+        }),
+    };
+
+    let mut mapping = Vec::new();
+    let mut sources = HashSet::new();
+    for record in reader.records() {
+        let record = record.map_err(|err| {
+            let line = err.position().map(|p| p.line());
+            parse_error(line, err.to_string())
+        })?;
+        let line = record.position().map(|p| p.line());
+        let (source, target) = match (record.get(0), record.get(1), record.len()) {
+            (Some(source), Some(target), 2) if !source.is_empty() && !target.is_empty() => {
+                (PathBuf::from(source), PathBuf::from(target))
+            }
+            _ => {
+                return Err(parse_error(
+                    line,
+                    "expected two non-empty columns".to_string(),
+                ));
+            }
+        };
+        if !sources.insert(source.clone()) {
+            return Err(parse_error(
+                line,
+                format!("source {} is repeated", source.display()),
+            ));
+        }
+        if source != target {
+            mapping.push((source, target));
+        }
+    }
+
+    Ok(mapping)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn read_mapping_file() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let mapfile = tempdir.path().join("mapping.csv");
+        let write = |content: &str| fs::write(&mapfile, content).unwrap();
+
+        write("old,new\n\"a, b.txt\",c.txt\nsame.txt,same.txt\n\"quoted \"\"name\"\"\",d.txt\n");
+        assert_eq!(
+            read_mapping(&mapfile, b',', true).unwrap(),
+            vec![
+                (PathBuf::from("a, b.txt"), PathBuf::from("c.txt")),
+                (PathBuf::from("quoted \"name\""), PathBuf::from("d.txt")),
+            ]
+        );
+
+        write("a,b.txt\tc.txt\n");
+        assert_eq!(
+            read_mapping(&mapfile, b'\t', false).unwrap(),
+            vec![(PathBuf::from("a,b.txt"), PathBuf::from("c.txt"))]
+        );
+
+        for content in ["a.txt,b.txt\nc.txt\n", "a.txt,b.txt,c.txt\n", "a.txt,\n"] {
+            write(content);
+            let error = read_mapping(&mapfile, b',', false).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::MapParse);
+        }
+        write("a.txt,b.txt\na.txt,c.txt\n");
+        let error = read_mapping(&mapfile, b',', false).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MapParse);
+        assert!(
+            error
+                .value
+                .unwrap()
+                .ends_with(":2: source a.txt is repeated")
+        );
+
+        let error = read_mapping(&tempdir.path().join("missing.csv"), b',', false).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ReadFile);
+    }
+}
@@ -14,6 +14,8 @@ enum PrinterMode {
 pub struct Printer {
     pub colors: Colors,
     mode: PrinterMode,
+    /// Print all messages to Stderr, keeping Stdout for other output
+    stderr: bool,
 }
 
 #[allow(dead_code)]
@@ -41,6 +43,7 @@ impl Printer {
         Printer {
             colors,
             mode: PrinterMode::Color { diff },
+            stderr: false,
         }
     }
 
@@ -58,6 +61,7 @@ impl Printer {
         Printer {
             colors,
             mode: PrinterMode::NoColor,
+            stderr: false,
         }
     }
 
@@ -75,12 +79,22 @@ impl Printer {
         Printer {
             colors,
             mode: PrinterMode::Silent,
+            stderr: false,
         }
     }
 
+    /// Return the same printer writing all messages to Stderr
+    pub fn with_stderr(mut self) -> Printer {
+        self.stderr = true;
+        self
+    }
+
     /// Print string to Stdout when printer is not in silent mode
     pub fn print(&self, message: &str) {
         match self.mode {
+            PrinterMode::Color { diff: _ } | PrinterMode::NoColor if self.stderr => {
+                eprintln!("{}", message);
+            }
             PrinterMode::Color { diff: _ } | PrinterMode::NoColor => {
                 println!("{}", message);
             }
@@ -110,6 +124,18 @@ impl Printer {
         ));
     }
 
+    /// Print warning pretty printed
+    pub fn print_warning(&self, warning: &Error) {
+        let warning_value = warning.value.to_owned().unwrap_or_else(|| String::from(""));
+
+        self.eprint(&format!(
+            "{}{}{}",
+            self.colors.warn.paint("Warning: "),
+            warning.description(),
+            self.colors.warn.paint(warning_value)
+        ));
+    }
+
     /// Pretty print operation
     pub fn print_operation(&self, source: &Path, target: &Path) {
         // Avoid any additional processing costs if silent mode
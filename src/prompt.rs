@@ -0,0 +1,194 @@
+use crate::error::*;
+use crate::output::Printer;
+use std::io::{BufRead, Write};
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+
+/// Answer to the confirmation of a rename.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+    Edit,
+}
+
+/// Ask for confirmation of each rename, given as source and target pairs, showing its operation.
+/// Renames can be accepted, skipped or edited one by one, and all the remaining ones can be
+/// accepted or skipped at once. Returns the accepted renames with their edited targets.
+pub fn select_renames(
+    printer: &Printer,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    renames: Vec<(PathBuf, PathBuf)>,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut selected = Vec::with_capacity(renames.len());
+    let mut renames = renames.into_iter();
+    while let Some((source, mut target)) = renames.next() {
+        loop {
+            printer.print_operation(&source, &target);
+            match ask_answer(input, output)? {
+                Answer::Yes => {
+                    selected.push((source, target));
+                    break;
+                }
+                Answer::No => break,
+                Answer::All => {
+                    selected.push((source, target));
+                    selected.extend(renames);
+                    return Ok(selected);
+                }
+                Answer::Quit => return Ok(selected),
+                // Show the edited operation to confirm it
+                Answer::Edit => {
+                    if let Some(name) = ask_name(input, output, &target)? {
+                        target = target.with_file_name(name);
+                    }
+                }
+            }
+        }
+    }
+    Ok(selected)
+}
+
+/// Ask a yes or no question. Any answer other than yes is taken as no.
+pub fn confirm(input: &mut dyn BufRead, output: &mut dyn Write, question: &str) -> Result<bool> {
+    let line = ask(input, output, question)?;
+    Ok(matches!(
+        line.as_deref().map(str::trim),
+        Some("y") | Some("yes")
+    ))
+}
+
+/// Ask for an answer until a valid one is read. The end of the input is taken as quitting.
+fn ask_answer(input: &mut dyn BufRead, output: &mut dyn Write) -> Result<Answer> {
+    loop {
+        let line = ask(
+            input,
+            output,
+            "Rename? [y]es, [n]o, [a]ll, [q]uit, [e]dit: ",
+        )?;
+        let answer = match line.as_deref().map(str::trim) {
+            None => Answer::Quit,
+            Some("y") | Some("yes") => Answer::Yes,
+            Some("n") | Some("no") => Answer::No,
+            Some("a") | Some("all") => Answer::All,
+            Some("q") | Some("quit") => Answer::Quit,
+            Some("e") | Some("edit") => Answer::Edit,
+            Some(_) => continue,
+        };
+        return Ok(answer);
+    }
+}
+
+/// Ask for a new file name for the target. An empty answer keeps the current one.
+fn ask_name(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    target: &Path,
+) -> Result<Option<String>> {
+    let current = target.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        let question = format!("New name [{}]: ", current);
+        let Some(name) = ask(input, output, &question)? else {
+            return Ok(None);
+        };
+        if name.is_empty() {
+            return Ok(None);
+        }
+        // Only the name can be edited, not the directory
+        if name == "." || name == ".." || name.contains(['/', MAIN_SEPARATOR]) {
+            write_prompt(output, "Invalid name, it cannot be a path\n")?;
+            continue;
+        }
+        return Ok(Some(name));
+    }
+}
+
+/// Write the question and read a line without its line break. Returns `None` at the end of the
+/// input.
+fn ask(input: &mut dyn BufRead, output: &mut dyn Write, question: &str) -> Result<Option<String>> {
+    write_prompt(output, question)?;
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(line.trim_end_matches(['\n', '\r']).to_string())),
+        Err(_) => Err(Error {
+            kind: ErrorKind::ReadFile,
+            value: Some("standard input".to_string()),
+        }),
+    }
+}
+
+/// Write a prompt message flushing the output.
+fn write_prompt(output: &mut dyn Write, message: &str) -> Result<()> {
+    match write!(output, "{}", message).and_then(|_| output.flush()) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error {
+            kind: ErrorKind::WriteFile,
+            value: Some("standard error".to_string()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn renames(names: &[(&str, &str)]) -> Vec<(PathBuf, PathBuf)> {
+        names
+            .iter()
+            .map(|(source, target)| (PathBuf::from(source), PathBuf::from(target)))
+            .collect()
+    }
+
+    #[test]
+    fn select_renames_answers() {
+        let printer = Printer::silent();
+        let select = |answers: &str, names: &[(&str, &str)]| {
+            let mut input = Cursor::new(answers.to_string());
+            let mut output = Vec::new();
+            select_renames(&printer, &mut input, &mut output, renames(names)).unwrap()
+        };
+        let names = [
+            ("dir/a.txt", "dir/a.md"),
+            ("dir/b.txt", "dir/b.md"),
+            ("dir/c.txt", "dir/c.md"),
+            ("dir/d.txt", "dir/d.md"),
+        ];
+
+        // Invalid answers and names are asked again
+        assert_eq!(
+            select("y\nx\nn\ne\nother/c\nnew.md\nyes\nq\n", &names),
+            renames(&[("dir/a.txt", "dir/a.md"), ("dir/c.txt", "dir/new.md")])
+        );
+        // Empty names keep the target
+        assert_eq!(
+            select("e\n\nn\na\n", &names),
+            renames(&[
+                ("dir/b.txt", "dir/b.md"),
+                ("dir/c.txt", "dir/c.md"),
+                ("dir/d.txt", "dir/d.md")
+            ])
+        );
+        // The end of the input skips the remaining renames
+        assert_eq!(select("y\n", &names), renames(&[("dir/a.txt", "dir/a.md")]));
+    }
+
+    #[test]
+    fn confirm_answers() {
+        let confirm_with = |answer: &str| {
+            let mut input = Cursor::new(answer.to_string());
+            let mut output = Vec::new();
+            let confirmed = confirm(&mut input, &mut output, "Apply? ").unwrap();
+            assert_eq!(output, b"Apply? ");
+            confirmed
+        };
+        assert!(confirm_with("y\n"));
+        assert!(confirm_with(" yes \n"));
+        assert!(!confirm_with("n\n"));
+        assert!(!confirm_with("\n"));
+        assert!(!confirm_with(""));
+    }
+}
@@ -1,24 +1,38 @@
+use crate::case::Case;
+use crate::cli::{NumberOrder, OnChange, Scope};
 use crate::config::{Config, ReplaceMode, RunMode};
 use crate::dumpfile;
+use crate::editor;
 use crate::error::*;
-use crate::fileutils::{cleanup_paths, create_backup, get_paths};
+use crate::fileutils::{
+    Fingerprint, cleanup_paths, create_backup, get_paths, natural_cmp, split_extension,
+};
+use crate::history;
+use crate::journal::{self, Journal, OperationState};
+use crate::mapfile;
+use crate::prompt;
 use crate::solver;
-use crate::solver::{Operation, Operations, RenameMap};
+use crate::solver::{Operation, OperationKind, Operations, RenameMap};
 use any_ascii::any_ascii;
 use rayon::prelude::*;
 use regex::Replacer;
-use std::fs;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 pub struct Renamer {
     config: Arc<Config>,
+    /// Fingerprints of the sources read when building the rename map.
+    fingerprints: Mutex<HashMap<PathBuf, Fingerprint>>,
 }
 
 impl Renamer {
     pub fn new(config: &Arc<Config>) -> Result<Renamer> {
         Ok(Renamer {
             config: config.clone(),
+            fingerprints: Mutex::new(HashMap::new()),
         })
     }
 
@@ -33,89 +47,551 @@ impl Renamer {
                 let clean_paths = cleanup_paths(input_paths, self.config.dirs);
 
                 // Relate original names with their targets
-                let rename_map = self.get_rename_map(&clean_paths)?;
+                let rename_map = match &self.config.replace_mode {
+                    ReplaceMode::Editor { command } => {
+                        self.get_edited_rename_map(command, &clean_paths)?
+                    }
+                    _ => self.get_rename_map(&clean_paths)?,
+                };
+                let rename_map = self.select_renames(rename_map)?;
 
                 // Solve renaming operation ordering to avoid conflicts
+                if self.config.full_path {
+                    solver::solve_move_order(&rename_map)?
+                } else {
+                    solver::solve_rename_order(&rename_map)?
+                }
+            }
+            RunMode::FromFile {
+                ref path,
+                undo,
+                ref rebase,
+            } => {
+                self.read_dump_operations(Path::new(path), undo, rebase.as_deref().map(Path::new))?
+            }
+            RunMode::Undo { ref path } => {
+                let printer = &self.config.printer;
+                printer.print(&format!(
+                    "{}Undoing batch '{}'",
+                    printer.colors.info.paint("Info: "),
+                    path
+                ));
+                self.read_dump_operations(Path::new(path), true, None)?
+            }
+            RunMode::History { ref dir } => {
+                let batches = history::read_history(dir)?;
+                history::print_history(&self.config.printer, &batches);
+                Operations::new()
+            }
+            RunMode::FromMap {
+                ref path,
+                delimiter,
+                header,
+            } => {
+                // Read and validate the paths of the mapping file
+                let mapping = mapfile::read_mapping(&PathBuf::from(path), delimiter, header)?;
+                let rename_map = self.get_mapped_rename_map(mapping)?;
+                let rename_map = self.select_renames(rename_map)?;
                 solver::solve_rename_order(&rename_map)?
             }
-            RunMode::FromFile { ref path, undo } => {
-                // Read operations from file
-                let operations = dumpfile::read_from_file(&PathBuf::from(path))?;
+            RunMode::Recover { ref path, undo } => {
+                // Read operations and their progress from the journal
+                let (operations, applied) = journal::read_journal(&PathBuf::from(path))?;
+                let printer = &self.config.printer;
+                printer.print(&format!(
+                    "{}{} of {} operations were applied",
+                    printer.colors.info.paint("Info: "),
+                    applied,
+                    operations.len()
+                ));
                 if undo {
-                    solver::revert_operations(&operations)?
+                    solver::revert_operations(&operations[..applied])?
                 } else {
-                    operations
+                    operations[applied..].to_vec()
                 }
             }
         };
 
+        Ok(operations)
+    }
+
+    /// Read the operations of a dump file, or their inverse ones to undo them, checking that they
+    /// can be applied to the current files.
+    fn read_dump_operations(
+        &self,
+        path: &Path,
+        undo: bool,
+        rebase: Option<&Path>,
+    ) -> Result<Operations> {
+        let mut dump = dumpfile::read_from_file(path)?;
+        dump.resolve_paths(rebase);
+        let operations = if undo {
+            solver::revert_operations(&dump.operations())?
+        } else {
+            dump.operations()
+        };
+        // Check that the dump is not stale before applying any operation
+        solver::validate_operations(&operations)?;
+        dump.check_fingerprints(undo)?;
+        Ok(operations)
+    }
+
+    /// Print the operations with their count and ask once for confirmation before applying them.
+    pub fn confirm_operations(&self, operations: &[Operation]) -> Result<bool> {
+        let printer = &self.config.printer;
+        let info = &printer.colors.info;
+        if operations.is_empty() {
+            return Ok(true);
+        }
+
+        for operation in operations {
+            self.print_operation(operation);
+        }
+        printer.print(&format!(
+            "{}{} operations to apply",
+            info.paint("Info: "),
+            operations.len()
+        ));
+        let confirmed = prompt::confirm(
+            &mut io::stdin().lock(),
+            &mut io::stderr(),
+            "Apply these operations? [y/N]: ",
+        )?;
+        if !confirmed {
+            printer.print(&format!("{}No files were renamed", info.paint("Info: ")));
+        }
+        Ok(confirmed)
+    }
+
+    /// Rename an operation batch. The progress is recorded in a journal to be able to recover an
+    /// interrupted batch. If rollback is enabled, completed operations are reverted when any of them
+    /// fails.
+    pub fn batch_rename(&self, operations: Operations) -> Result<()> {
+        let printer = &self.config.printer;
+        let colors = &printer.colors;
+
+        // Sources may have changed since the operations were solved
+        let operations = self.check_sources(operations)?;
+
         // Dump operations into a file if required
         if self.config.dump {
             dumpfile::dump_to_file(self.config.dump_prefix.clone(), &operations)?;
         }
 
-        Ok(operations)
+        let mut journal = if self.config.force && self.config.journal && !operations.is_empty() {
+            Some(Journal::create(&self.config.dump_prefix, &operations)?)
+        } else {
+            None
+        };
+
+        // Paths moved by the applied operations, which are not checked again
+        let mut moved: HashSet<&Path> = HashSet::new();
+        for (index, operation) in operations.iter().enumerate() {
+            let result = self.journaled_rename(index, operation, &moved, journal.as_mut());
+            moved.extend(source_paths(operation));
+            moved.insert(&operation.target);
+            if let Err(err) = result {
+                let err = if self.config.force && self.config.rollback {
+                    self.rollback(&operations[..index], err, journal.as_mut())
+                } else {
+                    err
+                };
+                if let Some(journal) = journal {
+                    if err.kind == ErrorKind::Rollback {
+                        journal.finish()?;
+                    } else {
+                        printer.print(&format!(
+                            "{}Batch interrupted, use the journal '{}' to recover it",
+                            colors.info.paint("Info: "),
+                            journal.path().display()
+                        ));
+                    }
+                }
+                return Err(err);
+            }
+        }
+
+        if let Some(journal) = journal {
+            journal.finish()?;
+        }
+        // The interrupted batch is already completed or undone
+        if let RunMode::Recover { ref path, .. } = self.config.run_mode
+            && self.config.force
+        {
+            journal::remove_journal(Path::new(path))?;
+        }
+        // Avoid undoing the batch twice
+        if let RunMode::Undo { ref path } = self.config.run_mode
+            && self.config.force
+        {
+            dumpfile::mark_undone(Path::new(path))?;
+        }
+        Ok(())
     }
 
-    /// Rename an operation batch
-    pub fn batch_rename(&self, operations: Operations) -> Result<()> {
-        for operation in operations {
-            self.rename(&operation)?;
+    /// Check that the sources were not modified or replaced after building the rename map. When any
+    /// of them changed, the batch is refused or the operation is skipped with the ones depending on
+    /// it, like the ones moving a file to its path. Sources are checked again right before applying
+    /// each operation.
+    fn check_sources(&self, operations: Operations) -> Result<Operations> {
+        let printer = &self.config.printer;
+        if self.fingerprints.lock().unwrap().is_empty() {
+            return Ok(operations);
+        }
+
+        // Only paths in place before applying the operations were fingerprinted
+        let mut moved: HashSet<&Path> = HashSet::new();
+        let mut changed = Vec::with_capacity(operations.len());
+        for operation in &operations {
+            changed.push(self.changed_source(operation, &moved));
+            moved.extend(source_paths(operation));
+            moved.insert(&operation.target);
+        }
+        if !changed.contains(&true) {
+            return Ok(operations);
+        }
+
+        if self.config.on_change == OnChange::Refuse {
+            let changed_operations: Vec<String> = operations
+                .iter()
+                .zip(&changed)
+                .filter(|(_, changed)| **changed)
+                .map(|(operation, _)| describe(operation))
+                .collect();
+            return Err(Error {
+                kind: ErrorKind::ChangedSource,
+                value: Some(changed_operations.join("\n")),
+            });
+        }
+
+        let skipped = skipped_operations(&operations, &changed);
+        let mut remaining = Operations::with_capacity(operations.len());
+        for ((operation, changed), skipped) in operations.into_iter().zip(changed).zip(skipped) {
+            if changed {
+                printer.print_warning(&Error {
+                    kind: ErrorKind::ChangedSource,
+                    value: Some(format!("{} (skipped)", describe(&operation))),
+                });
+            } else if skipped {
+                printer.eprint(&format!(
+                    "{}Skipped {}, it depends on a skipped operation",
+                    printer.colors.warn.paint("Warning: "),
+                    describe(&operation)
+                ));
+            } else {
+                remaining.push(operation);
+            }
+        }
+        Ok(remaining)
+    }
+
+    /// Check if any path of the operation in place before applying the batch was modified or
+    /// replaced after building the rename map. Paths already moved by the batch are not checked.
+    fn changed_source(&self, operation: &Operation, moved: &HashSet<&Path>) -> bool {
+        let fingerprints = self.fingerprints.lock().unwrap();
+        source_paths(operation).into_iter().any(|path| {
+            !moved.contains(path)
+                && fingerprints
+                    .get(path)
+                    .is_some_and(|fingerprint| !fingerprint.matches(path))
+        })
+    }
+
+    /// Rename the operation with the given index recording its progress in the journal.
+    fn journaled_rename(
+        &self,
+        index: usize,
+        operation: &Operation,
+        moved: &HashSet<&Path>,
+        mut journal: Option<&mut Journal>,
+    ) -> Result<()> {
+        // Sources may change while applying the previous operations
+        if self.changed_source(operation, moved) {
+            return Err(Error {
+                kind: ErrorKind::ChangedSource,
+                value: Some(describe(operation)),
+            });
+        }
+        if let Some(journal) = journal.as_mut() {
+            journal.record(index, OperationState::Pending)?;
+        }
+        self.rename(operation)?;
+        if let Some(journal) = journal {
+            journal.record(index, OperationState::Done)?;
         }
         Ok(())
     }
 
-    /// Replace file name matches in the given path using stored config.
-    fn replace_match(&self, path: &Path) -> PathBuf {
+    /// Revert completed operations after a failure in the batch. Returns an error reporting both
+    /// the original failure and the rollback result.
+    fn rollback(
+        &self,
+        completed: &[Operation],
+        error: Error,
+        mut journal: Option<&mut Journal>,
+    ) -> Error {
+        let printer = &self.config.printer;
+        let colors = &printer.colors;
+
+        let original_error = error.message();
+        printer.print(&format!(
+            "{}Rolling back {} operations",
+            colors.info.paint("Info: "),
+            completed.len()
+        ));
+
+        let operations = match solver::revert_operations(completed) {
+            Ok(operations) => operations,
+            Err(err) => {
+                return Error {
+                    kind: ErrorKind::RollbackFailed,
+                    value: Some(format!("{}\n{}", original_error, err.message())),
+                };
+            }
+        };
+        for (reverse_index, operation) in operations.iter().enumerate() {
+            // Operations are reverted from the last one
+            let index = completed.len() - 1 - reverse_index;
+            if let Some(journal) = journal.as_mut()
+                && let Err(err) = journal.record(index, OperationState::Reverting)
+            {
+                return Error {
+                    kind: ErrorKind::RollbackFailed,
+                    value: Some(format!("{}\n{}", original_error, err.message())),
+                };
+            }
+            if let Err(err) = operation.apply() {
+                return Error {
+                    kind: ErrorKind::RollbackFailed,
+                    value: Some(format!(
+                        "{}\n{} -> {}\n{}",
+                        original_error,
+                        operation.source.display(),
+                        operation.target.display(),
+                        err
+                    )),
+                };
+            }
+            self.print_operation(operation);
+            if let Some(journal) = journal.as_mut()
+                && let Err(err) = journal.record(index, OperationState::Reverted)
+            {
+                return Error {
+                    kind: ErrorKind::RollbackFailed,
+                    value: Some(format!("{}\n{}", original_error, err.message())),
+                };
+            }
+        }
+
+        Error {
+            kind: ErrorKind::Rollback,
+            value: Some(original_error),
+        }
+    }
+
+    /// Replace matches in the part of the current path selected by the scope of the replace mode.
+    /// Placeholders are rendered using the source path, which is the one in the filesystem, and
+    /// the index is its position in the numbering sequence.
+    fn replace_match(
+        &self,
+        mode: &ReplaceMode,
+        source: &Path,
+        path: &Path,
+        index: usize,
+    ) -> Result<PathBuf> {
         let file_name = path.file_name().unwrap().to_str().unwrap();
-        let parent = path.parent();
+        let replace = |text: &str, part: Scope| replace_text(mode, text, part, source, index);
 
-        let target_name = match &self.config.replace_mode {
-            ReplaceMode::RegExp {
-                expression,
-                replacement,
-                limit,
-                transform,
-            } => {
-                let replacer = TransformReplacer {
-                    replacement,
-                    transform: *transform,
+        let target_name = match mode.scope() {
+            Scope::Name => replace(file_name, Scope::Name)?,
+            Scope::Stem => {
+                let (stem, extension) = split_extension(file_name);
+                let stem = replace(stem, Scope::Stem)?;
+                match extension {
+                    Some(extension) => format!("{}.{}", stem, extension),
+                    None => stem,
+                }
+            }
+            Scope::Ext => match split_extension(file_name) {
+                (stem, Some(extension)) => {
+                    let extension = replace(extension, Scope::Ext)?;
+                    // Remove the dot if the extension is replaced by nothing
+                    if extension.is_empty() {
+                        stem.to_string()
+                    } else {
+                        format!("{}.{}", stem, extension)
+                    }
+                }
+                (_, None) => return Ok(path.to_path_buf()),
+            },
+            Scope::Path => {
+                let target = match mode {
+                    ReplaceMode::RegExp { .. } => {
+                        PathBuf::from(replace(path.to_str().unwrap(), Scope::Path)?)
+                    }
+                    // Transform each component as a file name
+                    _ => path
+                        .components()
+                        .map(|component| match component {
+                            Component::Normal(name) => {
+                                replace(name.to_str().unwrap(), Scope::Name).map(PathBuf::from)
+                            }
+                            _ => Ok(PathBuf::from(component.as_os_str())),
+                        })
+                        .collect::<Result<PathBuf>>()?,
                 };
-                expression
-                    .replacen(file_name, *limit, &replacer)
-                    .to_string()
+                if !self.config.full_path && target.parent() != path.parent() {
+                    return Err(Error {
+                        kind: ErrorKind::ChangeDirectory,
+                        value: Some(format!("{} -> {}", path.display(), target.display())),
+                    });
+                }
+                return Ok(target);
             }
-            ReplaceMode::ToASCII => to_ascii(file_name),
-            ReplaceMode::None => file_name.to_string(),
         };
 
-        match parent {
-            None => PathBuf::from(target_name),
-            Some(path) => path.join(Path::new(&target_name)),
+        match path.parent() {
+            None => Ok(PathBuf::from(target_name)),
+            Some(path) => Ok(path.join(Path::new(&target_name))),
         }
     }
 
-    /// Get hash map containing all replacements to be done
+    /// Get hash map containing all replacements to be done. Each step of the rules is applied to
+    /// the result of the previous one.
     fn get_rename_map(&self, paths: &[PathBuf]) -> Result<RenameMap> {
         let printer = &self.config.printer;
-        let colors = &printer.colors;
 
-        let mut rename_map = RenameMap::new();
-        let mut error_string = String::new();
+        let steps = match &self.config.replace_mode {
+            ReplaceMode::Rules(steps) => steps.iter().collect(),
+            mode => vec![mode],
+        };
+        let mut targets: Vec<(PathBuf, PathBuf)> =
+            paths.par_iter().map(|p| (p.clone(), p.clone())).collect();
+        for step in steps {
+            let counters = self.get_counters(step, &targets);
+            targets = targets
+                .into_par_iter()
+                .enumerate()
+                .filter_map(|(position, (source, path))| {
+                    let index = counters.get(&position).copied().unwrap_or_default();
+                    match self.replace_match(step, &source, &path, index) {
+                        Ok(target) => Some(Ok((source, target))),
+                        // Skip paths without values for all placeholders
+                        Err(err) if err.kind == ErrorKind::MissingValue => {
+                            printer.print_warning(&err);
+                            None
+                        }
+                        Err(err) => Some(Err(err)),
+                    }
+                })
+                .collect::<Result<_>>()?;
+        }
 
-        let targets: Vec<(PathBuf, PathBuf)> = paths
-            .into_par_iter()
-            .filter_map(|p| {
-                let target = self.replace_match(p);
-                // Discard paths with no changes
-                if *p != target {
-                    Some((p.clone(), target))
-                } else {
-                    None
-                }
-            })
+        // Discard paths with no changes
+        targets.retain(|(source, target)| source != target);
+        self.collect_rename_map(targets)
+    }
+
+    /// Get hash map containing the replacements done editing the list of paths in a text editor.
+    /// Each line of the edited list is the new path of the one in the same position.
+    fn get_edited_rename_map(&self, command: &str, paths: &[PathBuf]) -> Result<RenameMap> {
+        let bad_list = |message: String| Error {
+            kind: ErrorKind::EditedList,
+            value: Some(message),
+        };
+
+        if paths.is_empty() {
+            return Ok(RenameMap::new());
+        }
+        let lines: Vec<&str> = paths.iter().map(|p| p.to_str().unwrap()).collect();
+        if let Some(line) = lines.iter().find(|l| l.contains('\n')) {
+            return Err(bad_list(format!("path with line breaks {:?}", line)));
+        }
+        let edited_lines = editor::edit_lines(command, &lines)?;
+        if edited_lines.len() != lines.len() {
+            return Err(bad_list(format!(
+                "{} lines expected but {} found, lines cannot be added or deleted",
+                lines.len(),
+                edited_lines.len()
+            )));
+        }
+
+        let mut targets = Vec::new();
+        for (number, (source, line)) in paths.iter().zip(edited_lines).enumerate() {
+            let target = PathBuf::from(&line);
+            if line.is_empty() || target.file_name().is_none() {
+                return Err(bad_list(format!("line {} is not a valid path", number + 1)));
+            }
+            if *source == target {
+                continue;
+            }
+            if target.parent() != source.parent() {
+                return Err(Error {
+                    kind: ErrorKind::ChangeDirectory,
+                    value: Some(format!("{} -> {}", source.display(), target.display())),
+                });
+            }
+            targets.push((source.clone(), target));
+        }
+        self.collect_rename_map(targets)
+    }
+
+    /// Get hash map containing the replacements read from a mapping file. Sources must exist and
+    /// targets must be in the same directory, although it can be written differently.
+    fn get_mapped_rename_map(&self, mapping: Vec<(PathBuf, PathBuf)>) -> Result<RenameMap> {
+        let mut targets = Vec::with_capacity(mapping.len());
+        for (source, target) in mapping {
+            if source.symlink_metadata().is_err() {
+                return Err(Error {
+                    kind: ErrorKind::MissingSource,
+                    value: Some(source.display().to_string()),
+                });
+            }
+            let Some(file_name) = target.file_name() else {
+                return Err(Error {
+                    kind: ErrorKind::ChangeDirectory,
+                    value: Some(format!("{} -> {}", source.display(), target.display())),
+                });
+            };
+            if !same_directory(&source, &target) {
+                return Err(Error {
+                    kind: ErrorKind::ChangeDirectory,
+                    value: Some(format!("{} -> {}", source.display(), target.display())),
+                });
+            }
+            // Write the target as the source to compare them while solving the operations
+            let target = source.with_file_name(file_name);
+            targets.push((source, target));
+        }
+        self.collect_rename_map(targets)
+    }
+
+    /// Ask for confirmation of each rename sorted by source in interactive mode. Skipped renames are
+    /// removed before solving the operations, and edited targets are checked again.
+    fn select_renames(&self, rename_map: RenameMap) -> Result<RenameMap> {
+        if !self.config.interactive || rename_map.is_empty() {
+            return Ok(rename_map);
+        }
+        let mut renames: Vec<(PathBuf, PathBuf)> = rename_map
+            .into_iter()
+            .map(|(target, source)| (source, target))
             .collect();
+        renames.sort_unstable();
+        let selected = prompt::select_renames(
+            &self.config.printer,
+            &mut io::stdin().lock(),
+            &mut io::stderr(),
+            renames,
+        )?;
+        self.collect_rename_map(selected)
+    }
+
+    /// Collect source and target pairs into a rename map checking that targets are not duplicated.
+    fn collect_rename_map(&self, targets: Vec<(PathBuf, PathBuf)>) -> Result<RenameMap> {
+        let colors = &self.config.printer.colors;
 
+        let mut rename_map = RenameMap::new();
+        let mut error_string = String::new();
         for (source, target) in targets {
             // Targets cannot be duplicated by any reason
             if let Some(previous_source) = rename_map.get(&target) {
@@ -142,9 +618,80 @@ impl Renamer {
             });
         }
 
+        // Record the sources to detect changes before renaming them, keeping the first fingerprint
+        // when the map is collected again
+        let fingerprints: Vec<(PathBuf, Fingerprint)> = rename_map
+            .par_iter()
+            .filter_map(|(_, source)| Some((source.clone(), Fingerprint::read(source)?)))
+            .collect();
+        let mut recorded = self.fingerprints.lock().unwrap();
+        for (source, fingerprint) in fingerprints {
+            recorded.entry(source).or_insert(fingerprint);
+        }
+
         Ok(rename_map)
     }
 
+    /// Get the position of each path in the numbering sequence when counter placeholders are used,
+    /// indexed by the position of the path in the list. Paths are pairs of source and current path,
+    /// and only the ones matching the expression are numbered.
+    fn get_counters(
+        &self,
+        mode: &ReplaceMode,
+        paths: &[(PathBuf, PathBuf)],
+    ) -> HashMap<usize, usize> {
+        let expression = match mode {
+            ReplaceMode::RegExp {
+                expression,
+                replacement,
+                ..
+            } if replacement.has_counter() => expression,
+            _ => return HashMap::new(),
+        };
+
+        let scope = mode.scope();
+        let mut numbered_paths: Vec<(usize, &Path)> = paths
+            .par_iter()
+            .enumerate()
+            .map(|(position, (_, path))| (position, path.as_path()))
+            .filter(|(_, p)| scoped_text(p, scope).is_some_and(|t| expression.is_match(t)))
+            .collect();
+        match self.config.number_order {
+            NumberOrder::Path => numbered_paths.par_sort_unstable_by_key(|(_, p)| *p),
+            NumberOrder::Natural => numbered_paths.par_sort_unstable_by(|(_, a), (_, b)| {
+                natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+            }),
+            NumberOrder::Mtime => {
+                // Current paths may not exist yet, so times are read from sources
+                let mut timed_paths: Vec<(Option<SystemTime>, &Path, usize)> = numbered_paths
+                    .into_par_iter()
+                    .map(|(position, p)| {
+                        let source = &paths[position].0;
+                        let time = source.symlink_metadata().and_then(|m| m.modified()).ok();
+                        (time, p, position)
+                    })
+                    .collect();
+                timed_paths.par_sort_unstable();
+                numbered_paths = timed_paths.into_iter().map(|(_, p, i)| (i, p)).collect();
+            }
+        }
+
+        let mut counters = HashMap::with_capacity(numbered_paths.len());
+        let mut directory_counters: HashMap<&Path, usize> = HashMap::new();
+        for (index, (position, path)) in numbered_paths.into_iter().enumerate() {
+            let index = if self.config.number_per_dir {
+                let parent = path.parent().unwrap_or(Path::new(""));
+                let counter = directory_counters.entry(parent).or_default();
+                *counter += 1;
+                *counter - 1
+            } else {
+                index
+            };
+            counters.insert(position, index);
+        }
+        counters
+    }
+
     /// Rename path in the filesystem or simply print renaming information. Checks if target
     /// filename exists before renaming.
     fn rename(&self, operation: &Operation) -> Result<()> {
@@ -153,16 +700,20 @@ impl Renamer {
 
         if self.config.force {
             // Create a backup before actual renaming
-            if self.config.backup && !&operation.source.is_dir() {
-                match create_backup(&operation.source) {
+            let backup_paths = match operation.kind {
+                _ if !self.config.backup => vec![],
+                OperationKind::Rename => vec![&operation.source],
+                OperationKind::Exchange => vec![&operation.source, &operation.target],
+                OperationKind::CreateDir | OperationKind::RemoveDir => vec![],
+            };
+            for path in backup_paths.into_iter().filter(|p| !p.is_dir()) {
+                match create_backup(path) {
                     Ok(backup) => printer.print(&format!(
                         "{} Backup created - {}",
                         colors.info.paint("Info: "),
-                        colors.source.paint(format!(
-                            "{} -> {}",
-                            operation.source.display(),
-                            backup.display()
-                        ))
+                        colors
+                            .source
+                            .paint(format!("{} -> {}", path.display(), backup.display()))
                     )),
                     Err(err) => {
                         return Err(err);
@@ -171,26 +722,247 @@ impl Renamer {
             }
 
             // Rename paths in the filesystem
-            if let Err(err) = fs::rename(&operation.source, &operation.target) {
-                return Err(Error {
-                    kind: ErrorKind::Rename,
-                    value: Some(format!(
+            if let Err(err) = operation.apply() {
+                let kind = match operation.kind {
+                    // Another process created the target after solving the operations
+                    OperationKind::Rename if err.kind() == io::ErrorKind::AlreadyExists => {
+                        ErrorKind::TargetCreated
+                    }
+                    OperationKind::Rename | OperationKind::Exchange => ErrorKind::Rename,
+                    OperationKind::CreateDir => ErrorKind::CreateDir,
+                    OperationKind::RemoveDir => ErrorKind::RemoveDir,
+                };
+                let value = match operation.kind {
+                    OperationKind::Rename | OperationKind::Exchange => format!(
                         "{} -> {}\n{}",
                         operation.source.display(),
                         operation.target.display(),
                         err
-                    )),
+                    ),
+                    _ => format!("{}\n{}", operation.target.display(), err),
+                };
+                return Err(Error {
+                    kind,
+                    value: Some(value),
                 });
             } else {
-                printer.print_operation(&operation.source, &operation.target);
+                self.print_operation(operation);
             }
         } else {
             // Just print info in dry-run mode
-            printer.print_operation(&operation.source, &operation.target);
+            self.print_operation(operation);
         }
 
         Ok(())
     }
+
+    /// Print the operation. Exchanges are printed as two renames and directory operations as
+    /// information.
+    fn print_operation(&self, operation: &Operation) {
+        let printer = &self.config.printer;
+        let info = match operation.kind {
+            OperationKind::Rename => {
+                printer.print_operation(&operation.source, &operation.target);
+                return;
+            }
+            OperationKind::Exchange => {
+                printer.print_operation(&operation.source, &operation.target);
+                printer.print_operation(&operation.target, &operation.source);
+                return;
+            }
+            OperationKind::CreateDir => "Create directory ",
+            OperationKind::RemoveDir => "Remove directory ",
+        };
+        printer.print(&format!(
+            "{}{}{}",
+            printer.colors.info.paint("Info: "),
+            info,
+            printer
+                .colors
+                .target
+                .paint(operation.target.to_string_lossy())
+        ));
+    }
+}
+
+/// Replace the text of a part of the path. Transformations need to know which part it is to keep
+/// dots.
+fn replace_text(
+    mode: &ReplaceMode,
+    text: &str,
+    part: Scope,
+    source: &Path,
+    index: usize,
+) -> Result<String> {
+    let replaced = match mode {
+        ReplaceMode::RegExp {
+            expression,
+            replacement,
+            limit,
+            transform,
+            ..
+        } => {
+            // Avoid rendering placeholders if there is nothing to replace
+            if !expression.is_match(text) {
+                return Ok(text.to_string());
+            }
+            let replacement = replacement.render(source, index)?;
+            let replacer = TransformReplacer {
+                replacement: &replacement,
+                transform: *transform,
+            };
+            expression.replacen(text, *limit, &replacer).to_string()
+        }
+        ReplaceMode::ToASCII { .. } => to_ascii(text),
+        ReplaceMode::Case { transform, .. } => match part {
+            Scope::Stem => transform_stem(text, *transform),
+            Scope::Ext => transform_extension(text, *transform),
+            Scope::Name | Scope::Path => transform_file_name(text, *transform),
+        },
+        ReplaceMode::Rules(_) | ReplaceMode::Editor { .. } | ReplaceMode::None => text.to_string(),
+    };
+    Ok(replaced)
+}
+
+/// Describe an operation with its source and target.
+fn describe(operation: &Operation) -> String {
+    format!(
+        "{} -> {}",
+        operation.source.display(),
+        operation.target.display()
+    )
+}
+
+/// Get the paths that must be in place to apply an operation.
+fn source_paths(operation: &Operation) -> Vec<&Path> {
+    match operation.kind {
+        OperationKind::Rename => vec![&operation.source],
+        OperationKind::Exchange => vec![&operation.source, &operation.target],
+        OperationKind::CreateDir | OperationKind::RemoveDir => vec![],
+    }
+}
+
+/// Select the operations to skip because their sources changed or they depend on a skipped
+/// operation, like the ones moving a file to its path. Cycles broken with a temporary name are
+/// skipped as a whole, otherwise files would be left in temporary names. Exchanges are single
+/// operations, so they are already skipped as a whole.
+fn skipped_operations(operations: &[Operation], changed: &[bool]) -> Vec<bool> {
+    let cycles = cycle_groups(operations);
+    let mut skipped_cycles: HashSet<usize> = HashSet::new();
+    loop {
+        // Paths left in place and targets not created by skipped operations
+        let mut kept: HashSet<&Path> = HashSet::new();
+        let mut missing: HashSet<&Path> = HashSet::new();
+        let mut skipped = Vec::with_capacity(operations.len());
+        for ((operation, changed), cycle) in operations.iter().zip(changed).zip(&cycles) {
+            let depends = missing.contains(operation.source.as_path())
+                || match operation.kind {
+                    OperationKind::Rename => kept.contains(operation.target.as_path()),
+                    OperationKind::Exchange => missing.contains(operation.target.as_path()),
+                    OperationKind::CreateDir => false,
+                    OperationKind::RemoveDir => {
+                        kept.iter().any(|path| path.starts_with(&operation.target))
+                    }
+                };
+            let skip =
+                *changed || depends || cycle.is_some_and(|cycle| skipped_cycles.contains(&cycle));
+            if skip {
+                match operation.kind {
+                    OperationKind::Rename => {
+                        kept.insert(&operation.source);
+                        missing.insert(&operation.target);
+                    }
+                    OperationKind::Exchange => {
+                        kept.insert(&operation.source);
+                        kept.insert(&operation.target);
+                    }
+                    OperationKind::CreateDir | OperationKind::RemoveDir => {}
+                }
+            }
+            skipped.push(skip);
+        }
+
+        // Skipping a cycle can make other operations depend on it, so check them again
+        let count = skipped_cycles.len();
+        skipped_cycles.extend(
+            skipped
+                .iter()
+                .zip(&cycles)
+                .filter(|(skipped, _)| **skipped)
+                .filter_map(|(_, cycle)| *cycle),
+        );
+        if skipped_cycles.len() == count {
+            return skipped;
+        }
+    }
+}
+
+/// Get the cycle of each rename that is part of a cycle broken with a temporary name. Renames are
+/// linked to the ones creating their source and to the ones freeing their target, and the linked
+/// renames moving a file from a path created by the batch form a cycle.
+fn cycle_groups(operations: &[Operation]) -> Vec<Option<usize>> {
+    fn root(parents: &[usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            index = parents[index];
+        }
+        index
+    }
+    fn link(parents: &mut [usize], first: usize, second: usize) {
+        let first = root(parents, first);
+        let second = root(parents, second);
+        parents[first.max(second)] = first.min(second);
+    }
+    let mut parents: Vec<usize> = (0..operations.len()).collect();
+
+    let mut created: HashMap<&Path, usize> = HashMap::new();
+    let mut freed: HashMap<&Path, usize> = HashMap::new();
+    let mut temporary_sources = Vec::new();
+    for (index, operation) in operations.iter().enumerate() {
+        if operation.kind != OperationKind::Rename {
+            continue;
+        }
+        if let Some(&creator) = created.get(operation.source.as_path()) {
+            link(&mut parents, index, creator);
+            temporary_sources.push(index);
+        }
+        if let Some(&freer) = freed.get(operation.target.as_path()) {
+            link(&mut parents, index, freer);
+        }
+        created.insert(&operation.target, index);
+        freed.insert(&operation.source, index);
+    }
+
+    let cycles: HashSet<usize> = temporary_sources
+        .into_iter()
+        .map(|index| root(&parents, index))
+        .collect();
+    (0..operations.len())
+        .map(|index| Some(root(&parents, index)).filter(|cycle| cycles.contains(cycle)))
+        .collect()
+}
+
+/// Check if both paths are in the same directory, ignoring current directory components like in
+/// `./file` or `dir/./file`.
+fn same_directory(source: &Path, target: &Path) -> bool {
+    fn directory(path: &Path) -> impl Iterator<Item = Component<'_>> {
+        path.parent()
+            .unwrap_or(Path::new(""))
+            .components()
+            .filter(|component| *component != Component::CurDir)
+    }
+    directory(source).eq(directory(target))
+}
+
+/// Get the part of the path selected by the scope. Paths without extension do not have text in
+/// the extension scope.
+fn scoped_text(path: &Path, scope: Scope) -> Option<&str> {
+    let file_name = path.file_name().unwrap().to_str().unwrap();
+    match scope {
+        Scope::Name => Some(file_name),
+        Scope::Stem => Some(split_extension(file_name).0),
+        Scope::Ext => split_extension(file_name).1,
+        Scope::Path => path.to_str(),
+    }
 }
 
 /// Text tranformation type.
@@ -202,6 +974,8 @@ pub enum TextTransformation {
     Lower,
     /// To ASCII representation.
     Ascii,
+    /// To a case style.
+    Case(Case),
     /// Leave text as it is.
     None,
 }
@@ -212,6 +986,7 @@ impl TextTransformation {
             TextTransformation::Upper => text.to_uppercase(),
             TextTransformation::Lower => text.to_lowercase(),
             TextTransformation::Ascii => to_ascii(&text),
+            TextTransformation::Case(case) => case.convert(&text),
             TextTransformation::None => text,
         }
     }
@@ -229,6 +1004,34 @@ fn to_ascii(text: &str) -> String {
     any_ascii(text).replace("/", "_")
 }
 
+/// Transform a file name. Stem and extension are transformed separately.
+fn transform_file_name(file_name: &str, transform: TextTransformation) -> String {
+    match split_extension(file_name) {
+        (stem, Some(extension)) => format!(
+            "{}.{}",
+            transform_stem(stem, transform),
+            transform_extension(extension, transform)
+        ),
+        (stem, None) => transform_stem(stem, transform),
+    }
+}
+
+/// Transform a file stem keeping its leading dots.
+fn transform_stem(stem: &str, transform: TextTransformation) -> String {
+    let name = stem.trim_start_matches('.');
+    let dots = &stem[..stem.len() - name.len()];
+    format!("{}{}", dots, transform.transform(name.to_string()))
+}
+
+/// Transform each part of a file extension, e.g. `tar` and `gz`.
+fn transform_extension(extension: &str, transform: TextTransformation) -> String {
+    extension
+        .split('.')
+        .map(|part| transform.transform(part.to_string()))
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
 impl Replacer for &TransformReplacer<'_> {
     fn replace_append(&mut self, caps: &regex::Captures<'_>, dst: &mut String) {
         let mut replaced = String::default();
@@ -243,7 +1046,9 @@ mod test {
     extern crate tempfile;
     use super::*;
     use crate::config::RunMode;
+    use crate::fileutils::PathList;
     use crate::output::Printer;
+    use crate::template::Template;
     use regex::Regex;
     use std::fs;
     use std::path::Path;
@@ -254,12 +1059,21 @@ mod test {
         fn default() -> Self {
             Config {
                 force: true,
+                confirm: false,
                 backup: false,
+                rollback: false,
+                on_change: OnChange::Refuse,
+                interactive: false,
                 dirs: false,
                 dump: false,
                 dump_prefix: "rnr-".to_string(),
+                journal: false,
+                emit_script: None,
+                full_path: false,
                 run_mode: RunMode::Simple(vec![]),
                 replace_mode: ReplaceMode::None,
+                number_order: NumberOrder::Path,
+                number_per_dir: false,
                 printer: Printer::color(true),
             }
         }
@@ -333,9 +1147,10 @@ mod test {
             run_mode: RunMode::Simple(mock_files),
             replace_mode: ReplaceMode::RegExp {
                 expression: Regex::new("test").unwrap(),
-                replacement: "passed".to_string(),
+                replacement: Template::parse("passed").unwrap(),
                 limit: 1,
                 transform: TextTransformation::None,
+                scope: Scope::Name,
             },
             ..Config::default()
         });
@@ -371,9 +1186,10 @@ mod test {
             },
             replace_mode: ReplaceMode::RegExp {
                 expression: Regex::new("test").unwrap(),
-                replacement: "passed".to_string(),
+                replacement: Template::parse("passed").unwrap(),
                 limit: 1,
                 transform: TextTransformation::None,
+                scope: Scope::Name,
             },
             ..Config::default()
         });
@@ -412,9 +1228,10 @@ mod test {
             run_mode: RunMode::Simple(mock_files),
             replace_mode: ReplaceMode::RegExp {
                 expression: Regex::new("a").unwrap(),
-                replacement: "b".to_string(),
+                replacement: Template::parse("b").unwrap(),
                 limit: 0,
                 transform: TextTransformation::None,
+                scope: Scope::Name,
             },
             ..Config::default()
         });
@@ -442,7 +1259,7 @@ mod test {
 
         let mock_config = Arc::new(Config {
             run_mode: RunMode::Simple(mock_files),
-            replace_mode: ReplaceMode::ToASCII,
+            replace_mode: ReplaceMode::ToASCII { scope: Scope::Name },
             ..Config::default()
         });
 
@@ -480,5 +1297,781 @@ mod test {
         replacer.transform = TextTransformation::Ascii;
         let result = expression.replace(hay, &replacer);
         assert_eq!(result, "This.Is-a-File.txt");
+        // To a case style.
+        replacer.transform = TextTransformation::Case(Case::Snake);
+        let result = expression.replace(hay, &replacer);
+        assert_eq!(result, "thïs_îs_a_fïle.txt");
+    }
+
+    #[test]
+    fn case_style() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/My HTMLReport v2.PDF", temp_path),
+            format!("{}/.hidden fileName", temp_path),
+            format!("{}/Archive File.Tar.GZ", temp_path),
+        ];
+        for file in &mock_files {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Simple(mock_files),
+            replace_mode: ReplaceMode::Case {
+                transform: TextTransformation::Case(Case::Kebab),
+                scope: Scope::Stem,
+            },
+            ..Config::default()
+        });
+
+        run_with_config(mock_config);
+
+        // Check renamed files
+        assert!(Path::new(&format!("{}/my-html-report-v2.PDF", temp_path)).exists());
+        assert!(Path::new(&format!("{}/.hidden-file-name", temp_path)).exists());
+        assert!(Path::new(&format!("{}/archive-file.Tar.GZ", temp_path)).exists());
+
+        // Transform extensions too
+        assert_eq!(
+            transform_file_name("My HTMLReport v2.PDF", TextTransformation::Lower),
+            "my htmlreport v2.pdf"
+        );
+        assert_eq!(
+            transform_file_name("my report.PDF", TextTransformation::Case(Case::Title)),
+            "My Report.Pdf"
+        );
+    }
+
+    #[test]
+    fn replace_scope() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        fs::create_dir(format!("{}/photos", temp_path)).expect("Error creating mock dir...");
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/my.file.v2.jpeg", temp_path),
+            format!("{}/backup.tar.gz", temp_path),
+            format!("{}/photos/trip.jpeg", temp_path),
+        ];
+        for file in &mock_files {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+
+        let config = |expression: &str, replacement: &str, scope: Scope| {
+            Arc::new(Config {
+                run_mode: RunMode::Simple(mock_files.clone()),
+                replace_mode: ReplaceMode::RegExp {
+                    expression: Regex::new(expression).unwrap(),
+                    replacement: Template::parse(replacement).unwrap(),
+                    limit: 0,
+                    transform: TextTransformation::None,
+                    scope,
+                },
+                ..Config::default()
+            })
+        };
+
+        // Extensions are not affected in stem scope
+        let renamer = Renamer::new(&config(r"\.", "_", Scope::Stem)).unwrap();
+        let path = PathBuf::from(&mock_files[0]);
+        assert_eq!(
+            renamer
+                .replace_match(&renamer.config.replace_mode, &path, &path, 0)
+                .unwrap(),
+            tempdir.path().join("my_file_v2.jpeg")
+        );
+        let path = PathBuf::from(&mock_files[1]);
+        assert_eq!(
+            renamer
+                .replace_match(&renamer.config.replace_mode, &path, &path, 0)
+                .unwrap(),
+            path
+        );
+
+        // Only extensions are affected in extension scope
+        let renamer = Renamer::new(&config("^(jpeg|tar.gz)$", "x", Scope::Ext)).unwrap();
+        let path = PathBuf::from(&mock_files[1]);
+        assert_eq!(
+            renamer
+                .replace_match(&renamer.config.replace_mode, &path, &path, 0)
+                .unwrap(),
+            tempdir.path().join("backup.x")
+        );
+
+        // Full path can be matched but parent directories cannot change
+        run_with_config(config("photos/(.*)jpeg$", "photos/${1}jpg", Scope::Path));
+        assert!(Path::new(&format!("{}/photos/trip.jpg", temp_path)).exists());
+        assert!(Path::new(&format!("{}/my.file.v2.jpeg", temp_path)).exists());
+
+        let renamer = Renamer::new(&config("photos/", "", Scope::Path)).unwrap();
+        let path = tempdir.path().join("photos/trip.jpg");
+        let error = renamer
+            .replace_match(&renamer.config.replace_mode, &path, &path, 0)
+            .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ChangeDirectory);
+
+        // Transformations are applied to each part of the extension
+        let mock_config = Arc::new(Config {
+            replace_mode: ReplaceMode::Case {
+                transform: TextTransformation::Upper,
+                scope: Scope::Ext,
+            },
+            ..Config::default()
+        });
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let path = PathBuf::from(&mock_files[1]);
+        assert_eq!(
+            renamer
+                .replace_match(&renamer.config.replace_mode, &path, &path, 0)
+                .unwrap(),
+            tempdir.path().join("backup.TAR.GZ")
+        );
+    }
+
+    #[test]
+    fn move_files_full_path() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/Artist - Album - 01.mp3", temp_path),
+            format!("{}/Artist - Album - 02.mp3", temp_path),
+        ];
+        for file in &mock_files {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Simple(mock_files.clone()),
+            replace_mode: ReplaceMode::RegExp {
+                expression: Regex::new(r"(\w+) - (\w+) - (\d+)").unwrap(),
+                replacement: Template::parse("${1}/${2}/${3}").unwrap(),
+                limit: 1,
+                transform: TextTransformation::None,
+                scope: Scope::Path,
+            },
+            full_path: true,
+            ..Config::default()
+        });
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let operations = renamer.process().unwrap();
+        renamer.batch_rename(operations.clone()).unwrap();
+
+        // Check moved files
+        assert!(Path::new(&format!("{}/Artist/Album/01.mp3", temp_path)).exists());
+        assert!(Path::new(&format!("{}/Artist/Album/02.mp3", temp_path)).exists());
+
+        // Undo removes created directories
+        let operations = solver::revert_operations(&operations).unwrap();
+        renamer.batch_rename(operations).unwrap();
+        assert!(mock_files.iter().all(|f| Path::new(f).exists()));
+        assert!(!Path::new(&format!("{}/Artist", temp_path)).exists());
+    }
+
+    #[test]
+    fn chained_rules() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/Delivery FINAL (v2).JPEG", temp_path),
+            format!("{}/Ñandú photo.jpeg", temp_path),
+            format!("{}/notes.txt", temp_path),
+        ];
+        for file in &mock_files {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Simple(mock_files),
+            replace_mode: ReplaceMode::Rules(vec![
+                ReplaceMode::RegExp {
+                    expression: Regex::new(r"\s*\(v\d+\)").unwrap(),
+                    replacement: Template::parse("").unwrap(),
+                    limit: 0,
+                    transform: TextTransformation::None,
+                    scope: Scope::Stem,
+                },
+                ReplaceMode::ToASCII { scope: Scope::Name },
+                ReplaceMode::Case {
+                    transform: TextTransformation::Case(Case::Snake),
+                    scope: Scope::Name,
+                },
+                ReplaceMode::RegExp {
+                    expression: Regex::new("^jpeg$").unwrap(),
+                    replacement: Template::parse("jpg").unwrap(),
+                    limit: 1,
+                    transform: TextTransformation::None,
+                    scope: Scope::Ext,
+                },
+                // Only paths matching the expression in this step are numbered
+                ReplaceMode::RegExp {
+                    expression: Regex::new(r"(?s)^.*\.jpg$").unwrap(),
+                    replacement: Template::parse("{n:02}_$0").unwrap(),
+                    limit: 1,
+                    transform: TextTransformation::None,
+                    scope: Scope::Name,
+                },
+            ]),
+            ..Config::default()
+        });
+
+        run_with_config(mock_config);
+
+        // Check renamed files
+        assert!(Path::new(&format!("{}/01_delivery_final.jpg", temp_path)).exists());
+        assert!(Path::new(&format!("{}/02_nandu_photo.jpg", temp_path)).exists());
+        assert!(Path::new(&format!("{}/notes.txt", temp_path)).exists());
+    }
+
+    #[test]
+    fn rollback_failed_batch() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: PathList = vec![
+            [temp_path, "test_file_1.txt"].iter().collect(),
+            [temp_path, "test_file_2.txt"].iter().collect(),
+        ];
+        for file in &mock_files {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+        let renamed_files: PathList = vec![
+            [temp_path, "passed_file_1.txt"].iter().collect(),
+            [temp_path, "passed_file_2.txt"].iter().collect(),
+        ];
+        // Last operation fails because its source does not exist
+        let mut operations: Operations = mock_files
+            .iter()
+            .cloned()
+            .zip(renamed_files.iter().cloned())
+            .map(|(source, target)| Operation {
+                source,
+                target,
+                kind: OperationKind::Rename,
+            })
+            .collect();
+        operations.push(Operation {
+            source: [temp_path, "missing_file.txt"].iter().collect(),
+            target: [temp_path, "passed_missing_file.txt"].iter().collect(),
+            kind: OperationKind::Rename,
+        });
+
+        // Without rollback, completed operations are kept
+        let renamer = Renamer::new(&Arc::new(Config::default())).unwrap();
+        let error = renamer.batch_rename(operations.clone()).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Rename);
+        assert!(renamed_files.iter().all(|f| f.exists()));
+        for (source, target) in mock_files.iter().zip(renamed_files.iter()) {
+            fs::rename(target, source).expect("Error restoring mock file...");
+        }
+
+        // With rollback, the original tree is restored
+        let mock_config = Arc::new(Config {
+            rollback: true,
+            ..Config::default()
+        });
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let error = renamer.batch_rename(operations).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Rollback);
+        assert!(mock_files.iter().all(|f| f.exists()));
+        assert!(!renamed_files.iter().any(|f| f.exists()));
+    }
+
+    #[test]
+    fn recover_interrupted_batch() {
+        let (_temp_dir, temp_path, mock_files) = generate_file_tree();
+        println!("Running test in '{}'", temp_path);
+
+        let operations: Operations = mock_files
+            .iter()
+            .map(|f| Operation {
+                source: PathBuf::from(f),
+                target: PathBuf::from(f.replace("test_file", "passed_file")),
+                kind: OperationKind::Rename,
+            })
+            .collect();
+
+        // Simulate a batch interrupted in the middle of the second operation
+        let prefix = format!("{}/rnr-", temp_path);
+        let mut journal = Journal::create(&prefix, &operations).expect("Error creating journal.");
+        let journal_path = journal.path().to_string_lossy().to_string();
+        journal.record(0, OperationState::Pending).unwrap();
+        fs::rename(&operations[0].source, &operations[0].target).unwrap();
+        journal.record(0, OperationState::Done).unwrap();
+        journal.record(1, OperationState::Pending).unwrap();
+        fs::rename(&operations[1].source, &operations[1].target).unwrap();
+
+        // Undo applied operations
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Recover {
+                path: journal_path.clone(),
+                undo: true,
+            },
+            ..Config::default()
+        });
+        run_with_config(mock_config);
+        assert!(mock_files.iter().all(|f| Path::new(f).exists()));
+        assert!(!Path::new(&journal_path).exists());
+
+        // Complete the batch
+        let mut journal = Journal::create(&prefix, &operations).expect("Error creating journal.");
+        let journal_path = journal.path().to_string_lossy().to_string();
+        journal.record(0, OperationState::Pending).unwrap();
+        fs::rename(&operations[0].source, &operations[0].target).unwrap();
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Recover {
+                path: journal_path.clone(),
+                undo: false,
+            },
+            ..Config::default()
+        });
+        run_with_config(mock_config);
+        assert!(operations.iter().all(|o| o.target.exists()));
+        assert!(!operations.iter().any(|o| o.source.exists()));
+        assert!(!Path::new(&journal_path).exists());
+    }
+
+    #[test]
+    fn number_files() {
+        let (_temp_dir, temp_path, _) = generate_file_tree();
+        println!("Running test in '{}'", temp_path);
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/test_file_10.txt", temp_path),
+            format!("{}/test_file_9.txt", temp_path),
+            format!("{}/other_file.txt", temp_path),
+        ];
+        for file in &mock_files {
+            fs::write(file, file).expect("Error creating mock file...");
+        }
+
+        // Number files in natural order restarting the counter in each directory
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Recursive {
+                paths: vec![temp_path.clone()],
+                max_depth: None,
+                hidden: false,
+            },
+            replace_mode: ReplaceMode::RegExp {
+                expression: Regex::new(r"test_file_\d+").unwrap(),
+                replacement: Template::parse("file_{n:03}").unwrap(),
+                limit: 1,
+                transform: TextTransformation::None,
+                scope: Scope::Name,
+            },
+            number_order: NumberOrder::Natural,
+            number_per_dir: true,
+            ..Config::default()
+        });
+
+        run_with_config(mock_config);
+
+        // Check renamed files
+        assert!(Path::new(&format!("{}/file_001.txt", temp_path)).exists());
+        assert!(Path::new(&format!("{}/file_002.txt", temp_path)).exists());
+        assert!(Path::new(&format!("{}/file_003.txt", temp_path)).exists());
+        assert!(Path::new(&format!("{}/file_004.txt", temp_path)).exists());
+        assert!(Path::new(&format!("{}/other_file.txt", temp_path)).exists());
+        assert!(Path::new(&format!("{}/test_dir/file_001.txt", temp_path)).exists());
+        assert!(Path::new(&format!("{}/test_dir/file_002.txt", temp_path)).exists());
+
+        // Natural order: 1, 2, 9, 10
+        let content = fs::read_to_string(format!("{}/file_003.txt", temp_path)).unwrap();
+        assert_eq!(content, mock_files[1]);
+    }
+
+    #[test]
+    fn exif_skip_missing_tags() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/exif.jpg");
+        let mock_files: Vec<String> = vec![
+            format!("{}/photo.jpg", temp_path),
+            format!("{}/no_exif.jpg", temp_path),
+        ];
+        fs::copy(fixture, &mock_files[0]).expect("Error copying fixture file...");
+        fs::File::create(&mock_files[1]).expect("Error creating mock file...");
+
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Simple(mock_files),
+            replace_mode: ReplaceMode::RegExp {
+                expression: Regex::new(r".*\.jpg").unwrap(),
+                replacement: Template::parse("{exif.datetime:%Y%m%d}_{exif.model}.jpg").unwrap(),
+                limit: 1,
+                transform: TextTransformation::None,
+                scope: Scope::Name,
+            },
+            ..Config::default()
+        });
+
+        run_with_config(mock_config);
+
+        // Files without EXIF tags are skipped
+        assert!(Path::new(&format!("{}/20240517_Test Camera.jpg", temp_path)).exists());
+        assert!(Path::new(&format!("{}/no_exif.jpg", temp_path)).exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn edit_names() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/one.txt", temp_path),
+            format!("{}/two.txt", temp_path),
+            format!("{}/three.txt", temp_path),
+        ];
+        for file in &mock_files {
+            fs::write(file, file).expect("Error creating mock file...");
+        }
+
+        let config = |command: &str| {
+            Arc::new(Config {
+                run_mode: RunMode::Simple(mock_files.clone()),
+                replace_mode: ReplaceMode::Editor {
+                    command: command.to_string(),
+                },
+                ..Config::default()
+            })
+        };
+
+        // Lines cannot be deleted, moved to other directories or collide
+        for (command, kind) in [
+            ("sed -i 1d", ErrorKind::EditedList),
+            ("sed -i '1s/.*//'", ErrorKind::EditedList),
+            ("sed -i '1s|/one|/dir/one|'", ErrorKind::ChangeDirectory),
+            ("sed -i s/one/two/", ErrorKind::ExistingPath),
+            ("sed -i 's/one/four/;s/two/four/'", ErrorKind::SameFilename),
+        ] {
+            let mock_config = config(command);
+            let renamer = Renamer::new(&mock_config).unwrap();
+            assert_eq!(renamer.process().unwrap_err().kind, kind);
+        }
+        let mock_config = config("sed -i 's/two/one/;t;s/one/two/;s/three.txt/3.txt/'");
+        let renamer = Renamer::new(&mock_config).unwrap();
+
+        // Swap names and rename the last file
+        let operations = renamer.process().unwrap();
+        renamer.batch_rename(operations).unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("{}/one.txt", temp_path)).unwrap(),
+            mock_files[1]
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}/two.txt", temp_path)).unwrap(),
+            mock_files[0]
+        );
+        assert!(Path::new(&format!("{}/3.txt", temp_path)).exists());
+    }
+
+    #[test]
+    fn rename_from_map() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/one.txt", temp_path),
+            format!("{}/two.txt", temp_path),
+            format!("{}/three, four.txt", temp_path),
+        ];
+        for file in &mock_files {
+            fs::write(file, file).expect("Error creating mock file...");
+        }
+
+        let mapfile = format!("{}/mapping.tsv", temp_path);
+        let config = |content: &str| {
+            fs::write(&mapfile, content).expect("Error writing mapping file...");
+            Arc::new(Config {
+                run_mode: RunMode::FromMap {
+                    path: mapfile.clone(),
+                    delimiter: b'\t',
+                    header: true,
+                },
+                ..Config::default()
+            })
+        };
+
+        // Sources must exist, stay in the same directory and have unique targets
+        for (content, kind) in [
+            (
+                format!("old\tnew\n{0}/five.txt\t{0}/six.txt\n", temp_path),
+                ErrorKind::MissingSource,
+            ),
+            (
+                format!("old\tnew\n{0}/one.txt\t{0}/dir/one.txt\n", temp_path),
+                ErrorKind::ChangeDirectory,
+            ),
+            (
+                format!("old\tnew\n{0}/one.txt\t{0}/dir/../six.txt\n", temp_path),
+                ErrorKind::ChangeDirectory,
+            ),
+            (
+                format!("old\tnew\n{0}/one.txt\t{0}/two.txt\n", temp_path),
+                ErrorKind::ExistingPath,
+            ),
+            (
+                format!(
+                    "old\tnew\n{0}/one.txt\t{0}/six.txt\n{0}/two.txt\t{0}/six.txt\n",
+                    temp_path
+                ),
+                ErrorKind::SameFilename,
+            ),
+        ] {
+            let mock_config = config(&content);
+            let renamer = Renamer::new(&mock_config).unwrap();
+            assert_eq!(renamer.process().unwrap_err().kind, kind);
+        }
+
+        // Swap names and rename the last file
+        run_with_config(config(&format!(
+            "old\tnew\n{0}/one.txt\t{0}/two.txt\n{0}/two.txt\t{0}/one.txt\n\"{0}/three, four.txt\"\t{0}/3.txt\n",
+            temp_path
+        )));
+        assert_eq!(
+            fs::read_to_string(format!("{}/one.txt", temp_path)).unwrap(),
+            mock_files[1]
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}/two.txt", temp_path)).unwrap(),
+            mock_files[0]
+        );
+        assert!(Path::new(&format!("{}/3.txt", temp_path)).exists());
+
+        // Directories can be written differently
+        run_with_config(config(&format!(
+            "old\tnew\n{0}/one.txt\t{0}/./two.txt\n{0}//two.txt\t{0}/one.txt\n",
+            temp_path
+        )));
+        assert_eq!(
+            fs::read_to_string(format!("{}/one.txt", temp_path)).unwrap(),
+            mock_files[0]
+        );
+        run_with_config(config(&format!(
+            "old\tnew\n{0}/3.txt\t{0}/./4.txt\n",
+            temp_path
+        )));
+        assert!(Path::new(&format!("{}/4.txt", temp_path)).exists());
+        assert!(same_directory(Path::new("a.txt"), Path::new("./b.txt")));
+        assert!(same_directory(Path::new("dir/a"), Path::new("dir//b")));
+        assert!(!same_directory(Path::new("dir/a"), Path::new("dir/../b")));
+    }
+
+    #[test]
+    fn undo_from_history() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let history_dir = tempdir.path().join("history");
+
+        let mock_files: Vec<String> = vec![
+            format!("{}/a.txt", temp_path),
+            format!("{}/b.txt", temp_path),
+        ];
+        for file in &mock_files {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+
+        // Dumps are written in the history directory
+        run_with_config(Arc::new(Config {
+            dump: true,
+            dump_prefix: format!("{}/rnr-", history_dir.display()),
+            run_mode: RunMode::Simple(mock_files.clone()),
+            replace_mode: ReplaceMode::RegExp {
+                expression: Regex::new("txt").unwrap(),
+                replacement: Template::parse("md").unwrap(),
+                limit: 1,
+                transform: TextTransformation::None,
+                scope: Scope::Name,
+            },
+            ..Config::default()
+        }));
+        assert!(Path::new(&format!("{}/a.md", temp_path)).exists());
+
+        let batch = history::find_batch(&history_dir, None).unwrap();
+        assert_eq!(batch.id, 1);
+        run_with_config(Arc::new(Config {
+            run_mode: RunMode::Undo {
+                path: batch.path.to_string_lossy().to_string(),
+            },
+            ..Config::default()
+        }));
+        for file in &mock_files {
+            assert!(Path::new(file).exists());
+        }
+
+        // Batches cannot be undone twice
+        let error = history::find_batch(&history_dir, None).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::MissingBatch);
+        let error = history::find_batch(&history_dir, Some(1)).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::UndoneBatch);
+    }
+
+    #[test]
+    fn target_created_after_solving() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_files: Vec<String> = vec![format!("{}/a.txt", temp_path)];
+        fs::write(&mock_files[0], "a").expect("Error creating mock file...");
+
+        let mock_config = Arc::new(Config {
+            run_mode: RunMode::Simple(mock_files),
+            replace_mode: ReplaceMode::RegExp {
+                expression: Regex::new("a").unwrap(),
+                replacement: Template::parse("b").unwrap(),
+                limit: 1,
+                transform: TextTransformation::None,
+                scope: Scope::Name,
+            },
+            ..Config::default()
+        });
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let operations = renamer.process().unwrap();
+
+        // The target is not overwritten
+        let target = format!("{}/b.txt", temp_path);
+        fs::write(&target, "b").expect("Error creating mock file...");
+        let error = renamer.batch_rename(operations).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TargetCreated);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "b");
+    }
+
+    #[test]
+    fn changed_sources() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let path = |name: &str| format!("{}/{}", temp_path, name);
+        for name in ["a.txt", "b.txt", "d.txt"] {
+            fs::write(path(name), name).expect("Error creating mock file...");
+        }
+        let mapfile = path("mapping.csv");
+        fs::write(
+            &mapfile,
+            format!(
+                "{},{}\n{},{}\n{},{}\n",
+                path("a.txt"),
+                path("b.txt"),
+                path("b.txt"),
+                path("c.txt"),
+                path("d.txt"),
+                path("e.txt")
+            ),
+        )
+        .expect("Error writing mapping file...");
+        let config = |on_change: OnChange| {
+            Arc::new(Config {
+                on_change,
+                run_mode: RunMode::FromMap {
+                    path: mapfile.clone(),
+                    delimiter: b',',
+                    header: false,
+                },
+                ..Config::default()
+            })
+        };
+
+        // Replace a source after solving the operations
+        let mock_config = config(OnChange::Refuse);
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let operations = renamer.process().unwrap();
+        fs::remove_file(path("b.txt")).expect("Error removing mock file...");
+        fs::write(path("b.txt"), "replaced").expect("Error writing mock file...");
+        let error = renamer.batch_rename(operations).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ChangedSource);
+        assert_eq!(
+            error.value,
+            Some(format!("{} -> {}", path("b.txt"), path("c.txt")))
+        );
+        assert!(Path::new(&path("d.txt")).exists());
+
+        // Skip the changed source and the operation moving a file to its path
+        let mock_config = config(OnChange::Skip);
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let operations = renamer.process().unwrap();
+        fs::write(path("b.txt"), "modified").expect("Error writing mock file...");
+        renamer.batch_rename(operations).unwrap();
+        assert_eq!(fs::read_to_string(path("a.txt")).unwrap(), "a.txt");
+        assert_eq!(fs::read_to_string(path("b.txt")).unwrap(), "modified");
+        assert!(!Path::new(&path("c.txt")).exists());
+        assert_eq!(fs::read_to_string(path("e.txt")).unwrap(), "d.txt");
+    }
+
+    #[test]
+    fn changed_source_in_cycle() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let path = |name: &str| format!("{}/{}", temp_path, name);
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+            fs::write(path(name), name).expect("Error creating mock file...");
+        }
+        let mapfile = path("mapping.csv");
+        fs::write(
+            &mapfile,
+            format!(
+                "{},{}\n{},{}\n{},{}\n{},{}\n",
+                path("a.txt"),
+                path("b.txt"),
+                path("b.txt"),
+                path("c.txt"),
+                path("c.txt"),
+                path("a.txt"),
+                path("d.txt"),
+                path("e.txt")
+            ),
+        )
+        .expect("Error writing mapping file...");
+        let mock_config = Arc::new(Config {
+            on_change: OnChange::Skip,
+            run_mode: RunMode::FromMap {
+                path: mapfile.clone(),
+                delimiter: b',',
+                header: false,
+            },
+            ..Config::default()
+        });
+
+        // The cycle is skipped as a whole, even if its changed source is renamed last
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let operations = renamer.process().unwrap();
+        assert_eq!(
+            operations.last().unwrap().target,
+            PathBuf::from(path("a.txt"))
+        );
+        fs::write(path("a.txt"), "modified").expect("Error writing mock file...");
+        renamer.batch_rename(operations).unwrap();
+        assert_eq!(fs::read_to_string(path("a.txt")).unwrap(), "modified");
+        assert_eq!(fs::read_to_string(path("b.txt")).unwrap(), "b.txt");
+        assert_eq!(fs::read_to_string(path("c.txt")).unwrap(), "c.txt");
+        assert_eq!(fs::read_to_string(path("e.txt")).unwrap(), "d.txt");
+        // No temporary names are left besides the mapping file
+        assert_eq!(fs::read_dir(temp_path).unwrap().count(), 5);
+
+        // Sources are checked again right before applying each operation
+        fs::write(&mapfile, format!("{},{}\n", path("b.txt"), path("f.txt")))
+            .expect("Error writing mapping file...");
+        let renamer = Renamer::new(&mock_config).unwrap();
+        let operations = renamer.process().unwrap();
+        fs::write(path("b.txt"), "modified").expect("Error writing mock file...");
+        let error = renamer
+            .journaled_rename(0, &operations[0], &HashSet::new(), None)
+            .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ChangedSource);
+        assert!(!Path::new(&path("f.txt")).exists());
     }
 }
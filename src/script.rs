@@ -0,0 +1,272 @@
+use crate::cli::ScriptShell;
+use crate::fileutils::get_unique_filename;
+use crate::solver::{Operation, OperationKind};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Generate a script that applies the operations in order without overwriting any file. It checks
+/// that all the sources exist before applying the first operation.
+pub fn write_script(shell: ScriptShell, operations: &[Operation]) -> String {
+    let sources = initial_sources(operations);
+    let mut lines = Vec::new();
+    match shell {
+        ScriptShell::Sh => {
+            lines.push("#!/bin/sh".to_string());
+            lines.push(header_comment(operations.len()));
+            lines.push("set -e".to_string());
+            lines.push(String::new());
+            lines.push("# Check that all sources exist".to_string());
+            lines.push("for path in \\".to_string());
+            for source in &sources {
+                lines.push(format!("    {} \\", quote_sh(source)));
+            }
+            lines.push("; do".to_string());
+            lines.push("    if [ ! -e \"$path\" ] && [ ! -L \"$path\" ]; then".to_string());
+            lines.push("        echo \"Missing source: $path\" >&2".to_string());
+            lines.push("        exit 1".to_string());
+            lines.push("    fi".to_string());
+            lines.push("done".to_string());
+            lines.push(String::new());
+            // `mv -n` does not fail when the target exists
+            lines.push("# Rename failing if the target exists".to_string());
+            lines.push("rnr_mv() {".to_string());
+            lines.push("    if [ -e \"$2\" ] || [ -L \"$2\" ]; then".to_string());
+            lines.push("        echo \"Existing target: $2\" >&2".to_string());
+            lines.push("        exit 1".to_string());
+            lines.push("    fi".to_string());
+            lines.push("    mv -n -- \"$1\" \"$2\"".to_string());
+            lines.push("}".to_string());
+            lines.push(String::new());
+            for operation in expand_exchanges(operations) {
+                lines.push(match operation.kind {
+                    OperationKind::Rename | OperationKind::Exchange => format!(
+                        "rnr_mv {} {}",
+                        quote_sh(&operation.source),
+                        quote_sh(&operation.target)
+                    ),
+                    OperationKind::CreateDir => format!("mkdir -- {}", quote_sh(&operation.target)),
+                    OperationKind::RemoveDir => format!("rmdir -- {}", quote_sh(&operation.target)),
+                });
+            }
+        }
+        ScriptShell::Powershell => {
+            lines.push(header_comment(operations.len()));
+            lines.push("$ErrorActionPreference = 'Stop'".to_string());
+            lines.push(String::new());
+            lines.push("# Check that all sources exist".to_string());
+            lines.push("$sources = @(".to_string());
+            let count = sources.len();
+            for (index, source) in sources.iter().enumerate() {
+                let separator = if index + 1 < count { "," } else { "" };
+                lines.push(format!("    {}{}", quote_powershell(source), separator));
+            }
+            lines.push(")".to_string());
+            lines.push("foreach ($path in $sources) {".to_string());
+            lines.push("    if (-not (Test-Path -LiteralPath $path)) {".to_string());
+            lines.push("        Write-Error \"Missing source: $path\"".to_string());
+            lines.push("        exit 1".to_string());
+            lines.push("    }".to_string());
+            lines.push("}".to_string());
+            lines.push(String::new());
+            // `Move-Item` moves the source inside the target when it is a directory
+            lines.push("# Rename failing if the target exists".to_string());
+            lines.push("function Move-RnrItem($Source, $Target) {".to_string());
+            lines.push("    if (Test-Path -LiteralPath $Target) {".to_string());
+            lines.push("        throw \"Existing target: $Target\"".to_string());
+            lines.push("    }".to_string());
+            lines.push("    Move-Item -LiteralPath $Source -Destination $Target".to_string());
+            lines.push("}".to_string());
+            lines.push(String::new());
+            // `New-Item -Path` expands wildcards, and .NET resolves relative paths from the process
+            // directory instead of the current location
+            lines.push("# Create a directory failing if it exists".to_string());
+            lines.push("function New-RnrDirectory($Path) {".to_string());
+            lines.push("    if (Test-Path -LiteralPath $Path) {".to_string());
+            lines.push("        throw \"Existing target: $Path\"".to_string());
+            lines.push("    }".to_string());
+            lines.push(
+                "    $Path = $ExecutionContext.SessionState.Path.GetUnresolvedProviderPathFromPSPath($Path)"
+                    .to_string(),
+            );
+            lines.push("    [System.IO.Directory]::CreateDirectory($Path) | Out-Null".to_string());
+            lines.push("}".to_string());
+            lines.push(String::new());
+            for operation in expand_exchanges(operations) {
+                lines.push(match operation.kind {
+                    OperationKind::Rename | OperationKind::Exchange => format!(
+                        "Move-RnrItem {} {}",
+                        quote_powershell(&operation.source),
+                        quote_powershell(&operation.target)
+                    ),
+                    OperationKind::CreateDir => {
+                        format!("New-RnrDirectory {}", quote_powershell(&operation.target))
+                    }
+                    OperationKind::RemoveDir => format!(
+                        "Remove-Item -LiteralPath {}",
+                        quote_powershell(&operation.target)
+                    ),
+                });
+            }
+        }
+    }
+
+    let mut script = lines.join("\n");
+    script.push('\n');
+    script
+}
+
+/// Comment with the application version and the number of operations.
+fn header_comment(count: usize) -> String {
+    format!(
+        "# Generated by rnr {} ({} operations)",
+        env!("CARGO_PKG_VERSION"),
+        count
+    )
+}
+
+/// Get the paths that must exist before applying the operations, excluding the ones created by
+/// previous operations like temporary names.
+fn initial_sources(operations: &[Operation]) -> Vec<&Path> {
+    let mut created = HashSet::new();
+    let mut sources = Vec::new();
+    for operation in operations {
+        match operation.kind {
+            OperationKind::Rename | OperationKind::RemoveDir => {
+                if !created.contains(operation.source.as_path()) {
+                    sources.push(operation.source.as_path());
+                }
+            }
+            OperationKind::Exchange => {
+                for path in [&operation.source, &operation.target] {
+                    if !created.contains(path.as_path()) {
+                        sources.push(path.as_path());
+                    }
+                }
+            }
+            OperationKind::CreateDir => {}
+        }
+        created.insert(operation.target.as_path());
+    }
+    sources
+}
+
+/// Replace exchanges with three renames using a temporary name, since shells cannot swap files.
+fn expand_exchanges(operations: &[Operation]) -> Vec<Operation> {
+    let mut expanded = Vec::with_capacity(operations.len());
+    for operation in operations {
+        if operation.kind != OperationKind::Exchange {
+            expanded.push(operation.clone());
+            continue;
+        }
+        let temporary = get_unique_filename(&operation.source, ".rnr-tmp");
+        let rename = |source: &PathBuf, target: &PathBuf| Operation {
+            source: source.clone(),
+            target: target.clone(),
+            kind: OperationKind::Rename,
+        };
+        expanded.push(rename(&operation.source, &temporary));
+        expanded.push(rename(&operation.target, &operation.source));
+        expanded.push(rename(&temporary, &operation.target));
+    }
+    expanded
+}
+
+/// Quote a path for POSIX shells. Single quotes are closed, escaped and opened again.
+fn quote_sh(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// Quote a path for PowerShell. Single quotes are escaped doubling them.
+fn quote_powershell(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::process::{Command, Stdio};
+
+    fn operations() -> Vec<Operation> {
+        let operation = |source: &str, target: &str, kind: OperationKind| Operation {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+            kind,
+        };
+        vec![
+            operation("new", "new", OperationKind::CreateDir),
+            operation("it's.txt", "new/-a b.txt", OperationKind::Rename),
+            // Exchanges are applied through a temporary name
+            operation("a", "b", OperationKind::Exchange),
+        ]
+    }
+
+    #[test]
+    fn sh_script() {
+        let script = write_script(ScriptShell::Sh, &operations());
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(lines[0], "#!/bin/sh");
+        assert!(
+            script.contains("for path in \\\n    'it'\\''s.txt' \\\n    'a' \\\n    'b' \\\n; do")
+        );
+        assert_eq!(
+            &lines[lines.len() - 5..],
+            [
+                "mkdir -- 'new'",
+                "rnr_mv 'it'\\''s.txt' 'new/-a b.txt'",
+                "rnr_mv 'a' 'a.rnr-tmp'",
+                "rnr_mv 'b' 'a'",
+                "rnr_mv 'a.rnr-tmp' 'b'",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn sh_script_existing_target() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let path = |name: &str| tempdir.path().join(name);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(path(name), name).expect("Error creating mock file...");
+        }
+        let rename = |source: &str, target: &str| Operation {
+            source: path(source),
+            target: path(target),
+            kind: OperationKind::Rename,
+        };
+
+        // The script stops at the first existing target
+        let script = write_script(
+            ScriptShell::Sh,
+            &[rename("a.txt", "b.txt"), rename("c.txt", "d.txt")],
+        );
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&script)
+            .stderr(Stdio::null())
+            .status()
+            .expect("Error running script...");
+        assert!(!status.success());
+        assert_eq!(fs::read_to_string(path("b.txt")).unwrap(), "b.txt");
+        assert!(path("a.txt").exists());
+        assert!(path("c.txt").exists());
+        assert!(!path("d.txt").exists());
+    }
+
+    #[test]
+    fn powershell_script() {
+        let script = write_script(ScriptShell::Powershell, &operations());
+        let lines: Vec<&str> = script.lines().collect();
+        assert!(script.contains("$sources = @(\n    'it''s.txt',\n    'a',\n    'b'\n)"));
+        assert_eq!(
+            &lines[lines.len() - 5..],
+            [
+                "New-RnrDirectory 'new'",
+                "Move-RnrItem 'it''s.txt' 'new/-a b.txt'",
+                "Move-RnrItem 'a' 'a.rnr-tmp'",
+                "Move-RnrItem 'b' 'a'",
+                "Move-RnrItem 'a.rnr-tmp' 'b'",
+            ]
+        );
+    }
+}
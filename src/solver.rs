@@ -1,9 +1,13 @@
 use crate::error::*;
-use crate::fileutils::{PathList, is_same_file};
+#[cfg(target_os = "linux")]
+use crate::fileutils::renameat2;
+use crate::fileutils::{PathList, get_unique_filename, is_same_file, rename_no_replace};
 use path_abs::PathAbs;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use rayon::prelude::*;
 
@@ -14,10 +18,99 @@ pub type RenameMap = HashMap<PathBuf, PathBuf>;
 pub struct Operation {
     pub source: PathBuf,
     pub target: PathBuf,
+    #[serde(default, skip_serializing_if = "OperationKind::is_rename")]
+    pub kind: OperationKind,
+}
+
+/// Kind of operation. Directory operations have the same source and target. Exchange swaps the
+/// source and the target.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationKind {
+    #[default]
+    Rename,
+    Exchange,
+    CreateDir,
+    RemoveDir,
+}
+
+impl OperationKind {
+    fn is_rename(&self) -> bool {
+        *self == OperationKind::Rename
+    }
+}
+
+impl Operation {
+    /// Apply the operation in the filesystem.
+    pub fn apply(&self) -> io::Result<()> {
+        match self.kind {
+            OperationKind::Rename => rename_no_replace(&self.source, &self.target),
+            OperationKind::Exchange => exchange(&self.source, &self.target),
+            OperationKind::CreateDir => fs::create_dir(&self.target),
+            OperationKind::RemoveDir => fs::remove_dir(&self.target),
+        }
+    }
+
+    /// Get the inverse operation.
+    pub fn revert(&self) -> Operation {
+        Operation {
+            source: self.target.clone(),
+            target: self.source.clone(),
+            kind: match self.kind {
+                OperationKind::Rename => OperationKind::Rename,
+                OperationKind::Exchange => OperationKind::Exchange,
+                OperationKind::CreateDir => OperationKind::RemoveDir,
+                OperationKind::RemoveDir => OperationKind::CreateDir,
+            },
+        }
+    }
+
+    /// Check if the operation is already applied in the filesystem. Exchanges cannot be checked,
+    /// both paths exist before and after applying them, so they are considered not applied and the
+    /// journal identifies them by the fingerprint of the exchanged file instead.
+    pub fn is_applied(&self) -> bool {
+        match self.kind {
+            OperationKind::Rename => {
+                self.source.symlink_metadata().is_err() && self.target.symlink_metadata().is_ok()
+            }
+            OperationKind::Exchange => false,
+            OperationKind::CreateDir => self.target.is_dir(),
+            OperationKind::RemoveDir => self.target.symlink_metadata().is_err(),
+        }
+    }
 }
 
 pub type Operations = Vec<Operation>;
 
+/// Swap two paths. Linux exchanges them atomically with `renameat2`, other systems and file systems
+/// without support move the source to a temporary name first.
+fn exchange(source: &Path, target: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    match renameat2(source, target, libc::RENAME_EXCHANGE) {
+        // Unsupported by the kernel or the file system
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) => {}
+        result => return result,
+    }
+    exchange_with_temporary(source, target)
+}
+
+/// Swap two paths through a temporary name. If any rename fails, the ones already done are
+/// reverted, so the paths are left as they were whenever possible.
+fn exchange_with_temporary(source: &Path, target: &Path) -> io::Result<()> {
+    let temporary = get_unique_filename(source, ".rnr-tmp");
+    rename_no_replace(source, &temporary)?;
+    if let Err(err) = rename_no_replace(target, source) {
+        let _ = rename_no_replace(&temporary, source);
+        return Err(err);
+    }
+    if let Err(err) = rename_no_replace(&temporary, target) {
+        let _ =
+            rename_no_replace(source, target).and_then(|_| rename_no_replace(&temporary, source));
+        return Err(err);
+    }
+    Ok(())
+}
+
 /// Solve renaming order to avoid file overwrite. Solver will order the operations considering
 /// existing targets to avoid conflicts.
 pub fn solve_rename_order(rename_map: &RenameMap) -> Result<Operations> {
@@ -35,62 +128,236 @@ pub fn solve_rename_order(rename_map: &RenameMap) -> Result<Operations> {
     levels.sort_unstable();
     levels.reverse();
 
+    // Return existing targets in the list of original filenames. The filesystem is not modified
+    // while solving, so all levels can be checked at once.
+    let targets: PathList = rename_map.keys().cloned().collect();
+    let existing_targets: HashSet<PathBuf> = get_existing_targets(&targets, rename_map)?
+        .into_iter()
+        .collect();
+
     // Sort from deeper to higher path level
-    let mut rename_order = PathList::new();
+    let mut operations = Operations::with_capacity(rename_map.len());
     for level in levels {
         // Get all targets of this level
         let level_targets: Vec<PathBuf> = level_map.remove(&level).unwrap();
-
-        // Return existing targets in the list of original filenames
-        let mut existing_targets = get_existing_targets(&level_targets, rename_map)?;
+        let (mut level_existing_targets, level_free_targets): (PathList, PathList) = level_targets
+            .into_iter()
+            .partition(|p| existing_targets.contains(p));
 
         // Store first all non conflicting entries
-        rename_order.append(
-            &mut level_targets
-                .into_iter()
-                .filter_map(|p| {
-                    if !existing_targets.contains(&p) {
-                        Some(p)
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
-        );
+        operations.extend(level_free_targets.into_iter().map(|p| Operation {
+            source: rename_map[&p].clone(),
+            target: p,
+            kind: OperationKind::Rename,
+        }));
         // Order and append the rest of entries
-        match sort_existing_targets(rename_map, &mut existing_targets) {
-            Ok(mut targets) => rename_order.append(&mut targets),
+        match sort_existing_targets(rename_map, &mut level_existing_targets) {
+            Ok(mut level_operations) => operations.append(&mut level_operations),
             Err(err) => return Err(err),
         }
     }
 
-    // Construct a vector with the ordered operations
-    let mut operations = Operations::with_capacity(rename_order.len());
-    for target in rename_order {
-        operations.push(Operation {
-            source: rename_map[&target].clone(),
-            target,
-        });
+    Ok(operations)
+}
+
+/// Solve the order of operations that move files between directories. Missing parent directories
+/// of the targets are created first. Files can be blocked by sources in any directory level, so
+/// all existing targets are sorted together.
+pub fn solve_move_order(rename_map: &RenameMap) -> Result<Operations> {
+    let mut targets: PathList = rename_map.keys().cloned().collect();
+    targets.par_sort_unstable();
+
+    // Parent directories are sorted so they are created before their children
+    let mut directories: BTreeSet<PathBuf> = BTreeSet::new();
+    for target in &targets {
+        for parent in target.ancestors().skip(1) {
+            if parent.as_os_str().is_empty() || directories.contains(parent) {
+                break;
+            }
+            // A parent cannot be another target or an existing file
+            let is_file = match fs::metadata(parent) {
+                Ok(metadata) if metadata.is_dir() => break,
+                Ok(_) => true,
+                Err(_) => rename_map.contains_key(parent),
+            };
+            if is_file {
+                return Err(Error {
+                    kind: ErrorKind::ExistingPath,
+                    value: Some(format!(
+                        "{} -> {}",
+                        rename_map[target].display(),
+                        target.display()
+                    )),
+                });
+            }
+            directories.insert(parent.to_path_buf());
+        }
     }
+    let mut operations: Operations = directories
+        .into_iter()
+        .map(|directory| Operation {
+            source: directory.clone(),
+            target: directory,
+            kind: OperationKind::CreateDir,
+        })
+        .collect();
+
+    // Store first all non conflicting entries and order the rest
+    let existing_targets: HashSet<PathBuf> = get_existing_targets(&targets, rename_map)?
+        .into_iter()
+        .collect();
+    let (mut existing_targets, free_targets): (PathList, PathList) = targets
+        .into_iter()
+        .partition(|p| existing_targets.contains(p));
+    operations.extend(free_targets.into_iter().map(|p| Operation {
+        source: rename_map[&p].clone(),
+        target: p,
+        kind: OperationKind::Rename,
+    }));
+    operations.append(&mut sort_existing_targets(
+        rename_map,
+        &mut existing_targets,
+    )?);
 
     Ok(operations)
 }
 
 /// Revert the given operations. Returns operations in reverse order and with source/target
-/// fields interchanged.
+/// fields interchanged. Created directories are removed and vice versa.
 pub fn revert_operations(operations: &[Operation]) -> Result<Operations> {
-    let mut reverse_operations = operations.to_owned();
-    reverse_operations.reverse();
-    let inverse_operations = reverse_operations
-        .into_iter()
-        .map(|Operation { source, target }| Operation {
-            source: target,
-            target: source,
-        })
-        .collect();
+    let inverse_operations = operations.iter().rev().map(Operation::revert).collect();
     Ok(inverse_operations)
 }
 
+/// Check that a sequence of operations can be applied to the current filesystem, e.g. the
+/// operations read from a dump file. Operations are simulated in order, so paths created or moved
+/// away by previous operations are considered. All problems are reported at once.
+pub fn validate_operations(operations: &[Operation]) -> Result<()> {
+    // Operations touching each path, in ascending order
+    let mut touched: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (index, operation) in operations.iter().enumerate() {
+        touched.entry(&operation.source).or_default().push(index);
+        if operation.target != operation.source {
+            touched.entry(&operation.target).or_default().push(index);
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (index, operation) in operations.iter().enumerate() {
+        let source = resolve_path(&operation.source, index, operations, &touched);
+        let target = resolve_path(&operation.target, index, operations, &touched);
+        let problem = match operation.kind {
+            OperationKind::Rename | OperationKind::Exchange if !source.exists() => {
+                Some("Missing source")
+            }
+            OperationKind::Rename if target.exists() && !source.is_same_file(&target) => {
+                Some("Existing target")
+            }
+            OperationKind::Exchange if !target.exists() => Some("Missing target"),
+            OperationKind::CreateDir if target.exists() => Some("Existing directory"),
+            OperationKind::RemoveDir if !target.exists() => Some("Missing directory"),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            problems.push(match operation.kind {
+                OperationKind::Rename | OperationKind::Exchange => format!(
+                    "{}: {} -> {}",
+                    problem,
+                    operation.source.display(),
+                    operation.target.display()
+                ),
+                _ => format!("{}: {}", problem, operation.target.display()),
+            });
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error {
+            kind: ErrorKind::Preflight,
+            value: Some(problems.join("\n")),
+        })
+    }
+}
+
+/// State of a path before applying an operation.
+enum VirtualPath {
+    /// The path has not been touched by previous operations or it is the result of moving this
+    /// original path.
+    Original(PathBuf),
+    /// Directory created by a previous operation.
+    Created,
+    /// Path moved away or removed by a previous operation.
+    Missing,
+}
+
+impl VirtualPath {
+    fn exists(&self) -> bool {
+        match self {
+            VirtualPath::Original(path) => path.symlink_metadata().is_ok(),
+            VirtualPath::Created => true,
+            VirtualPath::Missing => false,
+        }
+    }
+
+    fn is_same_file(&self, other: &VirtualPath) -> bool {
+        match (self, other) {
+            (VirtualPath::Original(path), VirtualPath::Original(other)) => {
+                is_same_file(path, other)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Get the state of the path before applying the operation in the given position. Previous
+/// operations touching the path or any of its ancestors are undone from the latest one.
+fn resolve_path(
+    path: &Path,
+    position: usize,
+    operations: &[Operation],
+    touched: &HashMap<&Path, Vec<usize>>,
+) -> VirtualPath {
+    let mut path = path.to_path_buf();
+    let mut position = position;
+    loop {
+        // Latest operation touching the path or its ancestors before the current position
+        let latest = path
+            .ancestors()
+            .filter_map(|ancestor| {
+                let indexes = touched.get(ancestor)?;
+                let count = indexes.partition_point(|&i| i < position);
+                Some((*indexes.get(count.checked_sub(1)?)?, ancestor))
+            })
+            .max_by_key(|(index, _)| *index);
+        let Some((index, ancestor)) = latest else {
+            return VirtualPath::Original(path);
+        };
+
+        let operation = &operations[index];
+        let rest = path.strip_prefix(ancestor).unwrap().to_path_buf();
+        // Exchanges move the target to the source too
+        let origin = match operation.kind {
+            OperationKind::Rename | OperationKind::Exchange if ancestor == operation.target => {
+                &operation.source
+            }
+            OperationKind::Exchange if ancestor == operation.source => &operation.target,
+            OperationKind::CreateDir if rest.as_os_str().is_empty() => {
+                return VirtualPath::Created;
+            }
+            _ => return VirtualPath::Missing,
+        };
+        // Joining an empty path would add a trailing separator
+        path = if rest.as_os_str().is_empty() {
+            origin.clone()
+        } else {
+            origin.join(rest)
+        };
+        position = index;
+    }
+}
+
 /// Check if targets exist in the filesystem and return a list of them. If they exist, these
 /// targets must be contained in the original file list for the renaming problem to be solvable.
 fn get_existing_targets(targets: &[PathBuf], rename_map: &RenameMap) -> Result<PathList> {
@@ -101,9 +368,10 @@ fn get_existing_targets(targets: &[PathBuf], rename_map: &RenameMap) -> Result<P
         .map(|t| t.clone())
         .collect();
 
+    let sources: HashSet<&PathBuf> = rename_map.values().collect();
     let mut existing_targets = Vec::new();
     for target in files_in_fs {
-        if !rename_map.values().any(|x| x == &target) {
+        if !sources.contains(&target) {
             // The source and the target may be the same file in some conditions like case
             // insensitive but case-preserving file systems. In that case exclude that file without
             // any error.
@@ -124,50 +392,160 @@ fn get_existing_targets(targets: &[PathBuf], rename_map: &RenameMap) -> Result<P
     Ok(existing_targets)
 }
 
-/// Process the container with existing targets until it is empty. The algorithm extracts
-/// recursively all targets that are not present in a container with the sources exclusively related
-/// to current existing targets.
+/// Order the renaming of existing targets using a dependency graph. Every existing target is
+/// blocked, at most, by the operation that moves its current file away. Targets are sorted
+/// topologically, so the blocking operation is always performed first. When every remaining target
+/// is blocked, there is a cycle. Swaps of two paths are exchanged in a single operation and longer
+/// cycles are broken moving one of the sources to a temporary name first.
 fn sort_existing_targets(
     rename_map: &RenameMap,
     existing_targets: &mut PathList,
-) -> Result<PathList> {
-    let mut ordered_targets: PathList = Vec::new();
-
-    while !existing_targets.is_empty() {
-        // Track selected index to extract value
-        let mut selected_index: Option<usize> = None;
-
-        // Create a vector with all sources from existing targets using absolute paths
-        #[allow(clippy::needless_collect)] // Benchmark shows no diff, code is clearer this way.
-        let sources: PathList = existing_targets
-            .iter()
-            .map(|x| rename_map.get(x).cloned().unwrap())
-            .map(|p| PathAbs::new(p).unwrap().to_path_buf())
-            .collect();
+) -> Result<Operations> {
+    // Sort targets to get a deterministic order
+    existing_targets.par_sort_unstable();
 
-        // Select targets without conflicts in sources
-        for (index, target) in existing_targets.iter().enumerate() {
-            let absolute_target = PathAbs::new(target).unwrap().to_path_buf();
-            if !sources.contains(&absolute_target) {
-                selected_index = Some(index);
-                break;
-            }
+    // Index targets by the absolute path of their sources
+    let absolute = |p: &Path| PathAbs::new(p).unwrap().to_path_buf();
+    let source_index: HashMap<PathBuf, usize> = existing_targets
+        .par_iter()
+        .enumerate()
+        .map(|(index, target)| (absolute(&rename_map[target]), index))
+        .collect();
+
+    // Build the dependency graph: blocking operation -> blocked targets
+    let blockers: Vec<Option<usize>> = existing_targets
+        .par_iter()
+        .map(|target| source_index.get(&absolute(target)).copied())
+        .collect();
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); existing_targets.len()];
+    let mut pending_blockers: Vec<usize> = vec![0; existing_targets.len()];
+    for (index, blocker) in blockers.iter().enumerate() {
+        if let Some(blocker) = *blocker {
+            dependents[blocker].push(index);
+            pending_blockers[index] += 1;
         }
+    }
 
-        // Store result in ordered targets container or fail to stop the loop
-        match selected_index {
-            Some(index) => ordered_targets.push(existing_targets.swap_remove(index)),
-            // This will avoid infinite while loop if order is not solved
-            None => {
-                return Err(Error {
-                    kind: ErrorKind::SolveOrder,
-                    value: None,
-                });
+    // Start with all non blocked targets
+    let mut ready: VecDeque<usize> = (0..existing_targets.len())
+        .filter(|&index| pending_blockers[index] == 0)
+        .collect();
+    let mut done = vec![false; existing_targets.len()];
+    // Sources that have been moved to a temporary name indexed by their target position
+    let mut temporary_sources: HashMap<usize, PathBuf> = HashMap::new();
+    let mut temporary_names: HashSet<PathBuf> = HashSet::new();
+    let mut next_cycle_candidate = 0;
+    let mut solved = 0;
+
+    let mut ordered_operations = Operations::with_capacity(existing_targets.len());
+    while solved < existing_targets.len() {
+        // Store result in ordered operations container and release its dependents
+        if let Some(index) = ready.pop_front() {
+            // Targets released by an exchange are already done
+            if done[index] {
+                continue;
+            }
+            let target = existing_targets[index].clone();
+            let source = match temporary_sources.get(&index) {
+                Some(temporary) => temporary.clone(),
+                None => rename_map[&target].clone(),
+            };
+            ordered_operations.push(Operation {
+                source,
+                target,
+                kind: OperationKind::Rename,
+            });
+            done[index] = true;
+            solved += 1;
+            if !temporary_sources.contains_key(&index) {
+                release_dependents(index, &dependents, &mut pending_blockers, &mut ready);
             }
+            continue;
+        }
+
+        // Break the cycle moving the source of the first blocked target to a temporary name
+        while next_cycle_candidate < existing_targets.len()
+            && (done[next_cycle_candidate] || temporary_sources.contains_key(&next_cycle_candidate))
+        {
+            next_cycle_candidate += 1;
+        }
+        // This will avoid infinite while loop if order is not solved
+        if next_cycle_candidate == existing_targets.len() {
+            return Err(Error {
+                kind: ErrorKind::SolveOrder,
+                value: None,
+            });
+        }
+        let index = next_cycle_candidate;
+        let source = rename_map[&existing_targets[index]].clone();
+
+        // Exchange both paths when the target is blocked by its own blocked target
+        if let Some(blocker) = blockers[index]
+            && blockers[blocker] == Some(index)
+            && !done[blocker]
+            && !temporary_sources.contains_key(&blocker)
+        {
+            ordered_operations.push(Operation {
+                source,
+                target: existing_targets[index].clone(),
+                kind: OperationKind::Exchange,
+            });
+            done[index] = true;
+            done[blocker] = true;
+            solved += 2;
+            release_dependents(index, &dependents, &mut pending_blockers, &mut ready);
+            release_dependents(blocker, &dependents, &mut pending_blockers, &mut ready);
+            continue;
         }
+
+        let temporary = get_temporary_name(&source, rename_map, &temporary_names);
+        ordered_operations.push(Operation {
+            source,
+            target: temporary.clone(),
+            kind: OperationKind::Rename,
+        });
+        temporary_names.insert(temporary.clone());
+        temporary_sources.insert(index, temporary);
+        release_dependents(index, &dependents, &mut pending_blockers, &mut ready);
     }
 
-    Ok(ordered_targets)
+    Ok(ordered_operations)
+}
+
+/// Release the targets blocked by the source of the given target once it has been moved.
+fn release_dependents(
+    index: usize,
+    dependents: &[Vec<usize>],
+    pending_blockers: &mut [usize],
+    ready: &mut VecDeque<usize>,
+) {
+    for &dependent in &dependents[index] {
+        pending_blockers[dependent] -= 1;
+        if pending_blockers[dependent] == 0 {
+            ready.push_back(dependent);
+        }
+    }
+}
+
+/// Generate a temporary name next to the given source. It must not exist in the filesystem and it
+/// cannot conflict with any other target or temporary name of the renaming problem.
+fn get_temporary_name(
+    source: &Path,
+    rename_map: &RenameMap,
+    temporary_names: &HashSet<PathBuf>,
+) -> PathBuf {
+    let mut index = 0;
+    loop {
+        let suffix = match index {
+            0 => ".rnr-tmp".to_string(),
+            _ => format!(".rnr-tmp{}", index),
+        };
+        let temporary = get_unique_filename(source, &suffix);
+        if !rename_map.contains_key(&temporary) && !temporary_names.contains(&temporary) {
+            return temporary;
+        }
+        index += 1;
+    }
 }
 
 #[cfg(test)]
@@ -285,18 +663,19 @@ mod test {
             [temp_path, "aaaa.txt"].iter().collect(),
         ];
 
-        let ordered_targets = sort_existing_targets(&mock_rename_map, &mut mock_existing_targets)
-            .expect("Failed to order existing_targets.");
+        let ordered_operations =
+            sort_existing_targets(&mock_rename_map, &mut mock_existing_targets)
+                .expect("Failed to order existing_targets.");
         assert_eq!(
-            ordered_targets[0],
+            ordered_operations[0].target,
             [temp_path, "aaaa.txt"].iter().collect::<PathBuf>()
         );
         assert_eq!(
-            ordered_targets[1],
+            ordered_operations[1].target,
             [temp_path, "aaa.txt"].iter().collect::<PathBuf>()
         );
         assert_eq!(
-            ordered_targets[2],
+            ordered_operations[2].target,
             [temp_path, "aa.txt"].iter().collect::<PathBuf>()
         );
     }
@@ -339,4 +718,313 @@ mod test {
         assert_eq!(operations[3].target, mock_targets[1]);
         assert_eq!(operations[4].target, mock_targets[0]);
     }
+
+    /// Apply operations in the filesystem and check that every target contains the content of its
+    /// original source.
+    fn check_operations(operations: &[Operation], rename_map: &RenameMap) {
+        for operation in operations {
+            if operation.kind == OperationKind::Exchange {
+                operation.apply().expect("Error exchanging mock files.");
+                continue;
+            }
+            assert!(!operation.target.exists());
+            fs::rename(&operation.source, &operation.target).expect("Error renaming mock file.");
+        }
+        for (target, source) in rename_map {
+            let content = fs::read_to_string(target).expect("Error reading mock file.");
+            assert_eq!(content, source.to_string_lossy());
+        }
+    }
+
+    #[test]
+    fn test_solve_rename_order_swap() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_sources: PathList = vec![
+            [temp_path, "a.txt"].iter().collect(),
+            [temp_path, "b.txt"].iter().collect(),
+        ];
+        for file in &mock_sources {
+            fs::write(file, file.to_string_lossy().as_bytes()).expect("Error writing mock file.");
+        }
+
+        // Swap file names
+        let mock_targets: PathList = vec![
+            [temp_path, "b.txt"].iter().collect(),
+            [temp_path, "a.txt"].iter().collect(),
+        ];
+        let mock_rename_map: RenameMap = mock_targets.into_iter().zip(mock_sources).collect();
+
+        let operations =
+            solve_rename_order(&mock_rename_map).expect("Failed to solve rename order.");
+
+        // Both files are exchanged in a single operation
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].kind, OperationKind::Exchange);
+        assert_eq!(operations[0].source, PathBuf::from(temp_path).join("b.txt"));
+        assert_eq!(operations[0].target, PathBuf::from(temp_path).join("a.txt"));
+        check_operations(&operations, &mock_rename_map);
+    }
+
+    #[test]
+    fn test_exchange_operation() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let path = |name: &str| tempdir.path().join(name);
+        fs::write(path("a.txt"), "a").expect("Error writing mock file.");
+        fs::create_dir(path("b")).expect("Error creating mock dir.");
+
+        let operation = Operation {
+            source: path("a.txt"),
+            target: path("b"),
+            kind: OperationKind::Exchange,
+        };
+        validate_operations(std::slice::from_ref(&operation)).unwrap();
+        operation.apply().expect("Error exchanging mock files.");
+        assert!(path("a.txt").is_dir());
+        assert_eq!(fs::read_to_string(path("b")).unwrap(), "a");
+        assert!(!operation.is_applied());
+
+        let reverted = revert_operations(std::slice::from_ref(&operation)).unwrap();
+        assert_eq!(reverted[0].kind, OperationKind::Exchange);
+        reverted[0].apply().expect("Error exchanging mock files.");
+        assert_eq!(fs::read_to_string(path("a.txt")).unwrap(), "a");
+        assert!(path("b").is_dir());
+
+        // Exchanging through a temporary name restores the source when the target is missing
+        exchange_with_temporary(&path("a.txt"), &path("b")).expect("Error exchanging mock files.");
+        assert_eq!(fs::read_to_string(path("b")).unwrap(), "a");
+        exchange_with_temporary(&path("b"), &path("c.txt")).unwrap_err();
+        assert_eq!(fs::read_to_string(path("b")).unwrap(), "a");
+        assert!(!path("b.rnr-tmp").exists());
+        exchange_with_temporary(&path("a.txt"), &path("b")).expect("Error exchanging mock files.");
+
+        // Both paths must exist
+        let operations = vec![
+            operation.clone(),
+            Operation {
+                source: path("b"),
+                target: path("c.txt"),
+                kind: OperationKind::Exchange,
+            },
+        ];
+        let error = validate_operations(&operations).unwrap_err();
+        assert!(error.value.unwrap().starts_with("Missing target: "));
+    }
+
+    #[test]
+    fn test_solve_rename_order_rotation() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_sources: PathList = vec![
+            [temp_path, "1.txt"].iter().collect(),
+            [temp_path, "2.txt"].iter().collect(),
+            [temp_path, "3.txt"].iter().collect(),
+            [temp_path, "4.txt"].iter().collect(),
+        ];
+        for file in &mock_sources {
+            fs::write(file, file.to_string_lossy().as_bytes()).expect("Error writing mock file.");
+        }
+        // Existing file with the first temporary name
+        fs::File::create([temp_path, "1.txt.rnr-tmp"].iter().collect::<PathBuf>())
+            .expect("Error creating mock file...");
+
+        // Rotate file names: 1 -> 2 -> 3 -> 1 and an additional chain 4 -> 5
+        let mock_targets: PathList = vec![
+            [temp_path, "2.txt"].iter().collect(),
+            [temp_path, "3.txt"].iter().collect(),
+            [temp_path, "1.txt"].iter().collect(),
+            [temp_path, "5.txt"].iter().collect(),
+        ];
+        let mock_rename_map: RenameMap = mock_targets.into_iter().zip(mock_sources).collect();
+
+        let operations =
+            solve_rename_order(&mock_rename_map).expect("Failed to solve rename order.");
+
+        assert_eq!(operations.len(), 5);
+        check_operations(&operations, &mock_rename_map);
+    }
+
+    #[test]
+    fn test_solve_move_order() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        fs::create_dir([temp_path, "a"].iter().collect::<PathBuf>())
+            .expect("Error creating mock dir...");
+
+        let mock_sources: PathList = vec![
+            [temp_path, "a", "1.txt"].iter().collect(),
+            [temp_path, "2.txt"].iter().collect(),
+            [temp_path, "a", "3.txt"].iter().collect(),
+        ];
+        for file in &mock_sources {
+            fs::write(file, file.to_string_lossy().as_bytes()).expect("Error writing mock file.");
+        }
+
+        // Move files between directories, with a target blocked in a different level
+        let mock_targets: PathList = vec![
+            [temp_path, "b", "c", "1.txt"].iter().collect(),
+            [temp_path, "a", "1.txt"].iter().collect(),
+            [temp_path, "b", "3.txt"].iter().collect(),
+        ];
+        let mock_rename_map: RenameMap = mock_targets.into_iter().zip(mock_sources).collect();
+
+        let operations = solve_move_order(&mock_rename_map).expect("Failed to solve move order.");
+
+        assert_eq!(operations.len(), 5);
+        assert_eq!(operations[0].kind, OperationKind::CreateDir);
+        assert_eq!(
+            operations[0].target,
+            [temp_path, "b"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(operations[1].kind, OperationKind::CreateDir);
+        assert_eq!(
+            operations[1].target,
+            [temp_path, "b", "c"].iter().collect::<PathBuf>()
+        );
+        for operation in &operations[..2] {
+            operation.apply().expect("Error creating mock dir...");
+        }
+        check_operations(&operations[2..], &mock_rename_map);
+
+        // Created directories are removed when reverted
+        let reverted = revert_operations(&operations).unwrap();
+        assert_eq!(reverted[4].kind, OperationKind::RemoveDir);
+        assert_eq!(reverted[4].target, operations[0].target);
+    }
+
+    #[test]
+    fn test_solve_move_order_collision() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+
+        let mock_sources: PathList = vec![
+            [temp_path, "1.txt"].iter().collect(),
+            [temp_path, "2.txt"].iter().collect(),
+        ];
+        for file in &mock_sources {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+
+        // A target cannot be the directory of another target
+        let mock_targets: PathList = vec![
+            [temp_path, "a"].iter().collect(),
+            [temp_path, "a", "2.txt"].iter().collect(),
+        ];
+        let mock_rename_map: RenameMap = mock_targets.into_iter().zip(mock_sources).collect();
+        let error = solve_move_order(&mock_rename_map).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ExistingPath);
+
+        // Neither an existing file
+        let mock_rename_map: RenameMap = vec![(
+            [temp_path, "1.txt", "2.txt"].iter().collect(),
+            [temp_path, "2.txt"].iter().collect(),
+        )]
+        .into_iter()
+        .collect();
+        let error = solve_move_order(&mock_rename_map).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ExistingPath);
+    }
+
+    #[test]
+    fn test_validate_operations() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let temp_path = tempdir.path().to_str().unwrap();
+        let path = |parts: &[&str]| -> PathBuf { [&[temp_path], parts].concat().iter().collect() };
+
+        fs::create_dir(path(&["dir"])).expect("Error creating mock dir...");
+        for file in [path(&["dir", "a.txt"]), path(&["b.txt"]), path(&["c.txt"])] {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+        let operation = |source: PathBuf, target: PathBuf, kind: OperationKind| Operation {
+            source,
+            target,
+            kind,
+        };
+
+        // Paths moved or created by previous operations are considered
+        let operations = vec![
+            operation(
+                path(&["dir", "a.txt"]),
+                path(&["dir", "x.txt"]),
+                OperationKind::Rename,
+            ),
+            operation(path(&["dir"]), path(&["dir2"]), OperationKind::Rename),
+            operation(path(&["new"]), path(&["new"]), OperationKind::CreateDir),
+            operation(
+                path(&["b.txt"]),
+                path(&["new", "b.txt"]),
+                OperationKind::Rename,
+            ),
+            operation(
+                path(&["dir2", "x.txt"]),
+                path(&["new", "x.txt"]),
+                OperationKind::Rename,
+            ),
+        ];
+        validate_operations(&operations).unwrap();
+        let reverted = revert_operations(&operations).unwrap();
+        assert_eq!(
+            validate_operations(&reverted).unwrap_err().kind,
+            ErrorKind::Preflight
+        );
+        for operation in &operations {
+            operation.apply().expect("Error applying mock operation...");
+        }
+        validate_operations(&reverted).unwrap();
+        validate_operations(&operations).unwrap_err();
+
+        // All problems are reported at once
+        let operations = vec![
+            operation(path(&["a.txt"]), path(&["d.txt"]), OperationKind::Rename),
+            operation(path(&["c.txt"]), path(&["e.txt"]), OperationKind::Rename),
+            operation(path(&["e.txt"]), path(&["new"]), OperationKind::Rename),
+            operation(path(&["dir2"]), path(&["dir2"]), OperationKind::CreateDir),
+            operation(path(&["g"]), path(&["g"]), OperationKind::RemoveDir),
+        ];
+        let error = validate_operations(&operations).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Preflight);
+        let problems: Vec<String> = error.value.unwrap().lines().map(String::from).collect();
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].starts_with("Missing source: "));
+        assert!(problems[1].starts_with("Existing target: "));
+        assert!(problems[2].starts_with("Existing directory: "));
+        assert!(problems[3].starts_with("Missing directory: "));
+    }
+
+    /// A single long chain of renaming operations (`0 -> 1 -> 2 -> ...`) is the worst case for
+    /// ordering. Every target must be released before renaming into it.
+    #[test]
+    fn test_solve_long_rename_chain() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let temp_path = tempdir.path().to_str().unwrap();
+        let size = 5_000;
+
+        let mock_sources: PathList = (0..size)
+            .map(|i| [temp_path, &format!("{}.txt", i)].iter().collect())
+            .collect();
+        for file in &mock_sources {
+            fs::File::create(file).expect("Error creating mock file...");
+        }
+        let mock_targets: PathList = (1..=size)
+            .map(|i| [temp_path, &format!("{}.txt", i)].iter().collect())
+            .collect();
+        let mock_rename_map: RenameMap = mock_targets.into_iter().zip(mock_sources).collect();
+
+        let operations =
+            solve_rename_order(&mock_rename_map).expect("Failed to solve rename order.");
+        assert_eq!(operations.len(), size);
+        assert_eq!(
+            operations[0].target,
+            PathBuf::from(temp_path).join(format!("{}.txt", size))
+        );
+        validate_operations(&operations).unwrap();
+    }
 }
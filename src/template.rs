@@ -0,0 +1,828 @@
+use crate::cli::ExifFallback;
+use crate::error::*;
+use crate::fileutils::human_size;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime};
+use exif::{Exif, In, Tag, Value};
+use lofty::config::ParseOptions;
+use lofty::file::{TaggedFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::Accessor;
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::{self, File, Metadata};
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Replacement template. It contains literal text, including capture group references, and
+/// placeholders that are rendered for each path before expanding the captures.
+///
+/// Supported placeholders:
+/// * `{n}`: sequence counter. Format can be set with `{n:WIDTH}`, padding with zeros, and
+///   `{n:start=START,step=STEP}`. Both options can be combined, e.g. `{n:03,start=10,step=5}`.
+/// * `{mtime}` and `{ctime}`: modification and status change (creation in Windows) time. Format
+///   can be set using `chrono` syntax, e.g. `{mtime:%Y%m%d}`. Default format is `%Y-%m-%d`.
+/// * `{size}`: file size in bytes or in a human readable format with `{size:human}`.
+/// * `{uid}`, `{user}`, `{gid}`, `{group}` and `{mode}`: file owner, group and permissions in octal
+///   format. Only available in Unix systems.
+/// * `{exif.datetime}`, `{exif.make}` and `{exif.model}`: image capture date and camera from EXIF
+///   tags. Date format can be set as in `{mtime}`. Missing tags skip the file with a warning,
+///   but the modification time can be used as date fallback.
+/// * `{tag.artist}`, `{tag.album}`, `{tag.title}`, `{tag.genre}`, `{tag.track}` and `{tag.disc}`:
+///   audio tags (ID3, Vorbis comments, APE, MP4...). Numbers can be padded with `{tag.track:02}`.
+///   Missing tags skip the file with a warning.
+/// * `{hash}`: file content hash in hexadecimal. The algorithm (`sha256`, `sha512` or `blake3`) and
+///   length can be set, e.g. `{hash:blake3:12}`. Default algorithm is `sha256`. Directories and
+///   unreadable files are skipped with a warning.
+///
+/// Any `/` in the rendered values is replaced by `_` to avoid conflicts with paths, and any `$` is
+/// escaped so it is not expanded as a capture group reference. Braces that do not contain a known
+/// placeholder, or that are part of a capture group reference like `${1}`, are kept as literal
+/// text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Counter(Counter),
+    FileInfo(FileInfo),
+    Exif(ExifInfo),
+    Tag(TagInfo),
+    Hash(Hash),
+}
+
+/// Content hash placeholder
+#[derive(Clone, Debug, PartialEq)]
+struct Hash {
+    algorithm: HashAlgorithm,
+    length: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Length of the digest in hexadecimal digits.
+    fn hex_length(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Sha512 => 128,
+        }
+    }
+}
+
+/// Audio tag placeholder
+#[derive(Clone, Debug, PartialEq)]
+enum TagInfo {
+    Artist,
+    Album,
+    Title,
+    Genre,
+    Track { width: usize },
+    Disc { width: usize },
+}
+
+/// EXIF tag placeholder
+#[derive(Clone, Debug, PartialEq)]
+enum ExifInfo {
+    DateTime {
+        format: String,
+        fallback: ExifFallback,
+    },
+    Make,
+    Model,
+}
+
+/// File metadata placeholder
+#[derive(Clone, Debug, PartialEq)]
+enum FileInfo {
+    Modified(String),
+    Changed(String),
+    Size {
+        human: bool,
+    },
+    #[cfg(unix)]
+    Uid,
+    #[cfg(unix)]
+    User,
+    #[cfg(unix)]
+    Gid,
+    #[cfg(unix)]
+    Group,
+    #[cfg(unix)]
+    Mode,
+}
+
+/// Sequence counter placeholder
+#[derive(Clone, Debug, PartialEq)]
+struct Counter {
+    width: usize,
+    start: i64,
+    step: i64,
+}
+
+impl Template {
+    /// Parse the given replacement text.
+    pub fn parse(text: &str) -> Result<Template> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            // Capture group references like `${1}` are not placeholders
+            let is_capture_group = rest[..open].ends_with('$');
+            let close = match rest[open..].find('}') {
+                Some(close) => open + close,
+                None => break,
+            };
+            let placeholder = match is_capture_group {
+                true => None,
+                false => parse_placeholder(&rest[open + 1..close])?,
+            };
+
+            match placeholder {
+                Some(segment) => {
+                    literal.push_str(&rest[..open]);
+                    if !literal.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                }
+                None => literal.push_str(&rest[..=close]),
+            }
+            rest = &rest[close + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Text(literal));
+        }
+
+        Ok(Template { segments })
+    }
+
+    /// Check if the template contains any counter placeholder.
+    pub fn has_counter(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Counter(_)))
+    }
+
+    /// Set the fallback used when EXIF date is missing.
+    pub fn with_exif_fallback(mut self, exif_fallback: ExifFallback) -> Template {
+        for segment in self.segments.iter_mut() {
+            if let Segment::Exif(ExifInfo::DateTime { fallback, .. }) = segment {
+                *fallback = exif_fallback;
+            }
+        }
+        self
+    }
+
+    /// Render the template for the path in the given position of the numbering sequence.
+    pub fn render(&self, path: &Path, index: usize) -> Result<String> {
+        let mut rendered = String::new();
+        let mut file_data = FileData::new(path);
+
+        for segment in &self.segments {
+            let value = match segment {
+                Segment::Text(text) => {
+                    rendered.push_str(text);
+                    continue;
+                }
+                Segment::Counter(counter) => {
+                    let value = i64::try_from(index)
+                        .ok()
+                        .and_then(|index| counter.step.checked_mul(index))
+                        .and_then(|offset| counter.start.checked_add(offset))
+                        .ok_or_else(|| Error {
+                            kind: ErrorKind::BadPlaceholder,
+                            value: Some(format!(
+                                "{{n:start={},step={}}} overflows at position {}",
+                                counter.start, counter.step, index
+                            )),
+                        })?;
+                    format!("{:0width$}", value, width = counter.width)
+                }
+                Segment::FileInfo(info) => render_file_info(info, file_data.metadata()?),
+                Segment::Exif(info) => render_exif(info, &mut file_data)?,
+                Segment::Tag(info) => render_tag(info, &mut file_data)?,
+                Segment::Hash(hash) => {
+                    let digest = file_data.hash(hash.algorithm)?;
+                    digest[..hash.length.unwrap_or(digest.len())].to_string()
+                }
+            };
+            rendered.push_str(&value.replace('/', "_").replace('$', "$$"));
+        }
+        Ok(rendered)
+    }
+}
+
+/// File data required to render placeholders. Each kind of data is only read once, if required.
+struct FileData<'a> {
+    path: &'a Path,
+    metadata: Option<Metadata>,
+    exif: Option<Option<Exif>>,
+    audio: Option<Option<TaggedFile>>,
+    hashes: Vec<(HashAlgorithm, String)>,
+}
+
+impl<'a> FileData<'a> {
+    fn new(path: &'a Path) -> FileData<'a> {
+        FileData {
+            path,
+            metadata: None,
+            exif: None,
+            audio: None,
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Metadata of the file, following symlinks if they are not broken.
+    fn metadata(&mut self) -> Result<&Metadata> {
+        if self.metadata.is_none() {
+            let metadata =
+                match fs::metadata(self.path).or_else(|_| fs::symlink_metadata(self.path)) {
+                    Ok(metadata) => metadata,
+                    Err(_) => {
+                        return Err(Error {
+                            kind: ErrorKind::ReadFile,
+                            value: Some(self.path.to_string_lossy().to_string()),
+                        });
+                    }
+                };
+            self.metadata = Some(metadata);
+        }
+        Ok(self.metadata.as_ref().unwrap())
+    }
+
+    /// EXIF data of the file. Files that are not images or without EXIF data return `None`.
+    fn exif(&mut self) -> Option<&Exif> {
+        if self.exif.is_none() {
+            let exif = File::open(self.path).ok().and_then(|file| {
+                exif::Reader::new()
+                    .read_from_container(&mut BufReader::new(file))
+                    .ok()
+            });
+            self.exif = Some(exif);
+        }
+        self.exif.as_ref().unwrap().as_ref()
+    }
+
+    /// Audio tag of the file. The primary tag of the format is preferred over any other present.
+    /// Files that are not audio files or without tags return `None`.
+    fn audio_tag(&mut self) -> Option<&lofty::tag::Tag> {
+        if self.audio.is_none() {
+            let options = ParseOptions::new()
+                .read_properties(false)
+                .read_cover_art(false);
+            let audio = Probe::open(self.path)
+                .ok()
+                .and_then(|probe| probe.options(options).guess_file_type().ok())
+                .and_then(|probe| probe.read().ok());
+            self.audio = Some(audio);
+        }
+        let audio = self.audio.as_ref().unwrap().as_ref()?;
+        audio.primary_tag().or_else(|| audio.first_tag())
+    }
+
+    /// Content hash of the file in hexadecimal. Each algorithm is only computed once.
+    fn hash(&mut self, algorithm: HashAlgorithm) -> Result<&str> {
+        let position = match self.hashes.iter().position(|(a, _)| *a == algorithm) {
+            Some(position) => position,
+            None => {
+                if self.metadata()?.is_dir() {
+                    return Err(self.missing_value("hash"));
+                }
+                // Broken symlinks and unreadable files are skipped like directories
+                let digest =
+                    hash_file(self.path, algorithm).map_err(|_| self.missing_value("hash"))?;
+                self.hashes.push((algorithm, digest));
+                self.hashes.len() - 1
+            }
+        };
+        Ok(&self.hashes[position].1)
+    }
+
+    /// Error returned when the value of a placeholder is not available for this file.
+    fn missing_value(&self, placeholder: &str) -> Error {
+        Error {
+            kind: ErrorKind::MissingValue,
+            value: Some(format!("{{{}}} in {}", placeholder, self.path.display())),
+        }
+    }
+}
+
+/// Render an EXIF tag placeholder.
+fn render_exif(info: &ExifInfo, file_data: &mut FileData) -> Result<String> {
+    match info {
+        ExifInfo::DateTime { format, fallback } => {
+            let date = file_data.exif().and_then(|exif| {
+                [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+                    .into_iter()
+                    .filter_map(|tag| exif_text(exif, tag))
+                    .find_map(|t| NaiveDateTime::parse_from_str(&t, "%Y:%m:%d %H:%M:%S").ok())
+            });
+            match (date, fallback) {
+                (Some(date), _) => Ok(date.format(format).to_string()),
+                (None, ExifFallback::Mtime) => {
+                    Ok(format_time(file_data.metadata()?.modified().ok(), format))
+                }
+                (None, ExifFallback::Skip) => Err(file_data.missing_value("exif.datetime")),
+            }
+        }
+        ExifInfo::Make => match file_data.exif().and_then(|e| exif_text(e, Tag::Make)) {
+            Some(make) => Ok(make),
+            None => Err(file_data.missing_value("exif.make")),
+        },
+        ExifInfo::Model => match file_data.exif().and_then(|e| exif_text(e, Tag::Model)) {
+            Some(model) => Ok(model),
+            None => Err(file_data.missing_value("exif.model")),
+        },
+    }
+}
+
+/// Render an audio tag placeholder.
+fn render_tag(info: &TagInfo, file_data: &mut FileData) -> Result<String> {
+    let (name, value) = {
+        let tag = file_data.audio_tag();
+        match info {
+            TagInfo::Artist => ("tag.artist", tag.and_then(|t| tag_text(t.artist()))),
+            TagInfo::Album => ("tag.album", tag.and_then(|t| tag_text(t.album()))),
+            TagInfo::Title => ("tag.title", tag.and_then(|t| tag_text(t.title()))),
+            TagInfo::Genre => ("tag.genre", tag.and_then(|t| tag_text(t.genre()))),
+            TagInfo::Track { width } => (
+                "tag.track",
+                tag.and_then(|t| t.track())
+                    .map(|n| format!("{:0width$}", n, width = width)),
+            ),
+            TagInfo::Disc { width } => (
+                "tag.disc",
+                tag.and_then(|t| t.disk())
+                    .map(|n| format!("{:0width$}", n, width = width)),
+            ),
+        }
+    };
+    value.ok_or_else(|| file_data.missing_value(name))
+}
+
+/// Compute the hash of a file content and encode it in hexadecimal.
+fn hash_file(path: &Path, algorithm: HashAlgorithm) -> std::io::Result<String> {
+    let digest = match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            read_chunks(path, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        HashAlgorithm::Sha512 => {
+            let mut hasher = Sha512::new();
+            read_chunks(path, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            read_chunks(path, |chunk| {
+                hasher.update(chunk);
+            })?;
+            hasher.finalize().as_bytes().to_vec()
+        }
+    };
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Stream the file content in chunks to avoid loading large files in memory.
+fn read_chunks(path: &Path, mut consume: impl FnMut(&[u8])) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => consume(&buffer[..read]),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Get a trimmed audio tag text value, ignoring empty values.
+fn tag_text(value: Option<std::borrow::Cow<str>>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Get the text value of an EXIF tag in the primary image.
+fn exif_text(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|v| {
+                String::from_utf8_lossy(v)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string()
+            })
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+/// Render a file metadata placeholder.
+fn render_file_info(info: &FileInfo, metadata: &Metadata) -> String {
+    #[cfg(unix)]
+    use std::os::unix::fs::MetadataExt;
+
+    match info {
+        FileInfo::Modified(format) => format_time(metadata.modified().ok(), format),
+        FileInfo::Changed(format) => {
+            #[cfg(unix)]
+            let time = DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)
+                .map(SystemTime::from);
+            #[cfg(not(unix))]
+            let time = metadata.created().ok();
+            format_time(time, format)
+        }
+        FileInfo::Size { human: false } => metadata.len().to_string(),
+        FileInfo::Size { human: true } => human_size(metadata.len()),
+        #[cfg(unix)]
+        FileInfo::Uid => metadata.uid().to_string(),
+        #[cfg(unix)]
+        FileInfo::User => crate::fileutils::user_name(metadata.uid())
+            .unwrap_or_else(|| metadata.uid().to_string()),
+        #[cfg(unix)]
+        FileInfo::Gid => metadata.gid().to_string(),
+        #[cfg(unix)]
+        FileInfo::Group => crate::fileutils::group_name(metadata.gid())
+            .unwrap_or_else(|| metadata.gid().to_string()),
+        #[cfg(unix)]
+        FileInfo::Mode => format!("{:o}", metadata.mode() & 0o7777),
+    }
+}
+
+/// Format a time in the local timezone. Unavailable times are rendered as an empty string.
+fn format_time(time: Option<SystemTime>, format: &str) -> String {
+    match time {
+        Some(time) => DateTime::<Local>::from(time).format(format).to_string(),
+        None => String::new(),
+    }
+}
+
+/// Parse the content between braces. Returns `None` if it is not a known placeholder.
+fn parse_placeholder(content: &str) -> Result<Option<Segment>> {
+    let (name, options) = match content.split_once(':') {
+        Some((name, options)) => (name, Some(options)),
+        None => (content, None),
+    };
+
+    let bad_placeholder = || Error {
+        kind: ErrorKind::BadPlaceholder,
+        value: Some(format!("{{{}}}", content)),
+    };
+    let info = match (name, options) {
+        ("n", _) => return Ok(Some(Segment::Counter(parse_counter(content, options)?))),
+        ("mtime", _) => FileInfo::Modified(parse_time_format(content, options)?),
+        ("ctime", _) => FileInfo::Changed(parse_time_format(content, options)?),
+        ("exif.datetime", _) => {
+            return Ok(Some(Segment::Exif(ExifInfo::DateTime {
+                format: parse_time_format(content, options)?,
+                fallback: ExifFallback::Skip,
+            })));
+        }
+        ("exif.make", None) => return Ok(Some(Segment::Exif(ExifInfo::Make))),
+        ("exif.model", None) => return Ok(Some(Segment::Exif(ExifInfo::Model))),
+        ("tag.artist", None) => return Ok(Some(Segment::Tag(TagInfo::Artist))),
+        ("tag.album", None) => return Ok(Some(Segment::Tag(TagInfo::Album))),
+        ("tag.title", None) => return Ok(Some(Segment::Tag(TagInfo::Title))),
+        ("tag.genre", None) => return Ok(Some(Segment::Tag(TagInfo::Genre))),
+        ("tag.track", _) => {
+            let width = parse_width(options).ok_or_else(bad_placeholder)?;
+            return Ok(Some(Segment::Tag(TagInfo::Track { width })));
+        }
+        ("tag.disc", _) => {
+            let width = parse_width(options).ok_or_else(bad_placeholder)?;
+            return Ok(Some(Segment::Tag(TagInfo::Disc { width })));
+        }
+        ("hash", _) => return Ok(Some(Segment::Hash(parse_hash(content, options)?))),
+        ("size", None) => FileInfo::Size { human: false },
+        ("size", Some("human")) => FileInfo::Size { human: true },
+        #[cfg(unix)]
+        ("uid", None) => FileInfo::Uid,
+        #[cfg(unix)]
+        ("user", None) => FileInfo::User,
+        #[cfg(unix)]
+        ("gid", None) => FileInfo::Gid,
+        #[cfg(unix)]
+        ("group", None) => FileInfo::Group,
+        #[cfg(unix)]
+        ("mode", None) => FileInfo::Mode,
+        ("size" | "exif.make" | "exif.model", _) => return Err(bad_placeholder()),
+        ("tag.artist" | "tag.album" | "tag.title" | "tag.genre", _) => {
+            return Err(bad_placeholder());
+        }
+        #[cfg(unix)]
+        ("uid" | "user" | "gid" | "group" | "mode", _) => return Err(bad_placeholder()),
+        _ => return Ok(None),
+    };
+    Ok(Some(Segment::FileInfo(info)))
+}
+
+/// Parse and validate a time format. Default format is `%Y-%m-%d`.
+fn parse_time_format(content: &str, format: Option<&str>) -> Result<String> {
+    let format = format.unwrap_or("%Y-%m-%d");
+    if format.is_empty() || StrftimeItems::new(format).any(|i| matches!(i, Item::Error)) {
+        return Err(Error {
+            kind: ErrorKind::BadPlaceholder,
+            value: Some(format!("{{{}}}", content)),
+        });
+    }
+    Ok(format.to_string())
+}
+
+/// Parse an optional padding width. Returns `None` if it is not a valid number.
+fn parse_width(width: Option<&str>) -> Option<usize> {
+    match width {
+        Some(width) => width.parse().ok(),
+        None => Some(0),
+    }
+}
+
+/// Parse hash placeholder options: algorithm and, optionally, length.
+fn parse_hash(content: &str, options: Option<&str>) -> Result<Hash> {
+    let bad_placeholder = || Error {
+        kind: ErrorKind::BadPlaceholder,
+        value: Some(format!("{{{}}}", content)),
+    };
+
+    let (algorithm, length) = match options.map(|o| o.split_once(':')) {
+        None => ("sha256", None),
+        Some(None) => (options.unwrap(), None),
+        Some(Some((algorithm, length))) => (algorithm, Some(length)),
+    };
+    let algorithm = match algorithm {
+        "sha256" => HashAlgorithm::Sha256,
+        "sha512" => HashAlgorithm::Sha512,
+        "blake3" => HashAlgorithm::Blake3,
+        _ => return Err(bad_placeholder()),
+    };
+    let length = match length {
+        Some(length) => match length.parse() {
+            Ok(length) if length > 0 && length <= algorithm.hex_length() => Some(length),
+            _ => return Err(bad_placeholder()),
+        },
+        None => None,
+    };
+
+    Ok(Hash { algorithm, length })
+}
+
+/// Parse counter placeholder options.
+fn parse_counter(content: &str, options: Option<&str>) -> Result<Counter> {
+    let bad_placeholder = || Error {
+        kind: ErrorKind::BadPlaceholder,
+        value: Some(format!("{{{}}}", content)),
+    };
+
+    let mut counter = Counter {
+        width: 0,
+        start: 1,
+        step: 1,
+    };
+    for option in options.iter().flat_map(|o| o.split(',')) {
+        match option.split_once('=') {
+            Some(("start", value)) => {
+                counter.start = value.parse().map_err(|_| bad_placeholder())?
+            }
+            Some(("step", value)) => counter.step = value.parse().map_err(|_| bad_placeholder())?,
+            Some(_) => return Err(bad_placeholder()),
+            None => counter.width = option.parse().map_err(|_| bad_placeholder())?,
+        }
+    }
+
+    Ok(counter)
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempfile;
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn parse_literal() {
+        let template = Template::parse("${1}-{name}-$2.txt").unwrap();
+        assert!(!template.has_counter());
+        assert_eq!(
+            template.render(Path::new("file"), 0).unwrap(),
+            "${1}-{name}-$2.txt"
+        );
+    }
+
+    #[test]
+    fn render_counter() {
+        let path = Path::new("file");
+        let template = Template::parse("img_{n}.jpg").unwrap();
+        assert!(template.has_counter());
+        assert_eq!(template.render(path, 0).unwrap(), "img_1.jpg");
+        assert_eq!(template.render(path, 9).unwrap(), "img_10.jpg");
+
+        let template = Template::parse("img_{n:03}_${1}.jpg").unwrap();
+        assert_eq!(template.render(path, 0).unwrap(), "img_001_${1}.jpg");
+        assert_eq!(template.render(path, 41).unwrap(), "img_042_${1}.jpg");
+
+        let template = Template::parse("{n:start=10,step=5}-{n:04,start=0,step=-1}").unwrap();
+        assert_eq!(template.render(path, 0).unwrap(), "10-0000");
+        assert_eq!(template.render(path, 2).unwrap(), "20--002");
+    }
+
+    #[test]
+    fn bad_counter() {
+        assert!(Template::parse("{n:abc}").is_err());
+        assert!(Template::parse("{n:start=a}").is_err());
+        assert!(Template::parse("{n:begin=1}").is_err());
+
+        // Values out of range cannot be rendered
+        let template = Template::parse("{n:start=9223372036854775800,step=5}").unwrap();
+        assert!(template.render(Path::new("file"), 1).is_ok());
+        let error = template.render(Path::new("file"), 2).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::BadPlaceholder);
+        let template = Template::parse("{n:step=4611686018427387904}").unwrap();
+        assert!(template.render(Path::new("file"), 2).is_err());
+    }
+
+    #[test]
+    fn render_file_info() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let file = tempdir.path().join("test_file.txt");
+        fs::write(&file, vec![0; 1536]).expect("Error creating mock file...");
+
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        let date = DateTime::<Local>::from(modified);
+
+        let template = Template::parse("{mtime}_{mtime:%Y/%m}_{size}_{size:human}").unwrap();
+        assert_eq!(
+            template.render(&file, 0).unwrap(),
+            format!(
+                "{}_{}_1536_1.5K",
+                date.format("%Y-%m-%d"),
+                date.format("%Y_%m")
+            )
+        );
+        assert!(Template::parse("{ctime:%H%M%S}").is_ok());
+
+        // Values are not expanded as capture group references
+        let template = Template::parse("X{mtime:$1Y}").unwrap();
+        let rendered = template.render(&file, 0).unwrap();
+        assert_eq!(rendered, "X$$1Y");
+        assert_eq!(Regex::new(".*").unwrap().replace("test", &rendered), "X$1Y");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+            let template = Template::parse("{mode}-{uid}-{gid}").unwrap();
+            let metadata = fs::metadata(&file).unwrap();
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(
+                template.render(&file, 0).unwrap(),
+                format!("640-{}-{}", metadata.uid(), metadata.gid())
+            );
+            assert!(Template::parse("{user}{group}").is_ok());
+        }
+
+        // Missing files cannot be rendered
+        let missing = tempdir.path().join("missing_file.txt");
+        assert!(template.render(&missing, 0).is_err());
+    }
+
+    #[test]
+    fn bad_file_info() {
+        assert!(Template::parse("{mtime:}").is_err());
+        assert!(Template::parse("{mtime:%Q}").is_err());
+        assert!(Template::parse("{size:bytes}").is_err());
+    }
+
+    #[test]
+    fn render_exif() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let template =
+            Template::parse("{exif.datetime:%Y%m%d_%H%M%S}_{exif.make}_{exif.model}").unwrap();
+        assert_eq!(
+            template.render(&fixtures.join("exif.jpg"), 0).unwrap(),
+            "20240517_143005_RnR Optics_Test Camera"
+        );
+        assert_eq!(
+            template.render(&fixtures.join("exif.tif"), 0).unwrap(),
+            "20240517_143005_RnR Optics_Test Camera"
+        );
+
+        // Missing EXIF data
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let file = tempdir.path().join("test_file.jpg");
+        fs::write(&file, b"\xff\xd8\xff\xd9").expect("Error creating mock file...");
+
+        let template = Template::parse("{exif.datetime}").unwrap();
+        let error = template.render(&file, 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingValue);
+
+        let template = template.with_exif_fallback(ExifFallback::Mtime);
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        assert_eq!(
+            template.render(&file, 0).unwrap(),
+            DateTime::<Local>::from(modified)
+                .format("%Y-%m-%d")
+                .to_string()
+        );
+
+        // Only dates have a fallback
+        let template = Template::parse("{exif.model}")
+            .unwrap()
+            .with_exif_fallback(ExifFallback::Mtime);
+        let error = template.render(&file, 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn render_tag() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let template =
+            Template::parse("{tag.track:02} - {tag.artist} - {tag.album} - {tag.title}").unwrap();
+        assert_eq!(
+            template.render(&fixtures.join("tags.mp3"), 0).unwrap(),
+            "03 - AC_DC - Live Album - Track Title"
+        );
+        assert_eq!(
+            template.render(&fixtures.join("tags.flac"), 0).unwrap(),
+            "07 - Some Artist - Some Album - Some_Title"
+        );
+        let template = Template::parse("{tag.disc}-{tag.track}").unwrap();
+        assert_eq!(
+            template.render(&fixtures.join("tags.flac"), 0).unwrap(),
+            "2-7"
+        );
+
+        // Missing tags
+        let template = Template::parse("{tag.genre}").unwrap();
+        assert_eq!(
+            template.render(&fixtures.join("tags.mp3"), 0).unwrap(),
+            "Rock"
+        );
+        let error = template.render(&fixtures.join("tags.flac"), 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingValue);
+
+        // Files without tags
+        let error = template.render(&fixtures.join("exif.jpg"), 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingValue);
+
+        assert!(Template::parse("{tag.track:ab}").is_err());
+        assert!(Template::parse("{tag.title:02}").is_err());
+    }
+
+    #[test]
+    fn render_hash() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        println!("Running test in '{:?}'", tempdir);
+        let file = tempdir.path().join("test_file.txt");
+        fs::write(&file, b"abc").expect("Error creating mock file...");
+
+        let template = Template::parse("{hash}").unwrap();
+        assert_eq!(
+            template.render(&file, 0).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let template = Template::parse("{hash:sha256:8}_{hash:blake3:12}_{hash:sha512:6}").unwrap();
+        assert_eq!(
+            template.render(&file, 0).unwrap(),
+            "ba7816bf_6437b3ac3846_ddaf35"
+        );
+
+        // Directories cannot be hashed
+        let error = template.render(tempdir.path(), 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MissingValue);
+
+        // Neither broken symlinks
+        #[cfg(unix)]
+        {
+            let symlink = tempdir.path().join("broken_link");
+            std::os::unix::fs::symlink(tempdir.path().join("missing"), &symlink)
+                .expect("Error creating mock symlink...");
+            let error = template.render(&symlink, 0).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::MissingValue);
+        }
+    }
+
+    #[test]
+    fn bad_hash() {
+        assert!(Template::parse("{hash:md5}").is_err());
+        assert!(Template::parse("{hash:sha256:0}").is_err());
+        assert!(Template::parse("{hash:sha256:65}").is_err());
+        assert!(Template::parse("{hash:blake3:ab}").is_err());
+        assert!(Template::parse("{hash:sha512:128}").is_ok());
+    }
+}